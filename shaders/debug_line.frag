@@ -0,0 +1,10 @@
+#version 430 core
+
+layout(location = 0) in vec4 line_color;
+
+layout(location = 0) out vec4 output_col;
+
+void main()
+{
+    output_col = line_color;
+}
@@ -0,0 +1,23 @@
+#version 430 core
+
+layout(location = 0) in vec3 input_pos;
+layout(location = 1) in vec4 input_col;
+layout(location = 2) in float input_size;
+
+layout(location = 0) out vec4 line_color;
+
+layout(std140, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec3 camera_position;
+    float time;
+};
+
+// Lines and points given in world space, each vertex with its own colour
+void main()
+{
+    gl_Position = view_projection * vec4(input_pos, 1.0);
+    gl_PointSize = input_size;
+    line_color = input_col;
+}
@@ -0,0 +1,19 @@
+#version 430 core
+
+layout(location = 1) in vec4 input_col;
+layout(location = 2) in vec3 input_normal;
+layout(location = 3) in vec3 fragNormal;
+layout(location = 4) in vec3 fragPosition;
+
+layout(location = 0) out vec4 output_col;
+layout(location = 1) out vec3 output_normal;
+layout(location = 2) out vec3 output_ambient;
+
+// The world space normal mapped from [-1, 1] to a colour, instead of any lighting
+void main()
+{
+    vec3 normalizedNormal = normalize(fragNormal);
+    output_col = vec4(normalizedNormal * 0.5 + 0.5, 1.0);
+    output_normal = normalizedNormal;
+    output_ambient = vec3(0.0); // Nothing for the ambient occlusion pass to darken
+}
@@ -0,0 +1,16 @@
+#version 430 core
+
+layout(location = 0) in vec3 input_pos;
+layout(location = 2) in vec3 input_normal;
+
+layout(location = 0) out vec3 world_position;
+layout(location = 1) out vec3 world_normal;
+
+uniform mat4 model_matrix;
+
+// Only moved into the world, the geometry shader makes lines of the vertices
+void main()
+{
+    world_position = vec3(model_matrix * vec4(input_pos, 1.0));
+    world_normal = normalize(mat3(transpose(inverse(model_matrix))) * input_normal);
+}
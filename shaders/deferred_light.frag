@@ -0,0 +1,36 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+layout(location = 1) out vec3 output_normal;
+layout(location = 2) out vec3 output_ambient;
+
+uniform sampler2D albedo_buffer;
+uniform sampler2D normal_buffer;
+uniform sampler2D position_buffer;
+uniform vec4 sky_color;
+
+// From lighting.frag
+vec3 moon_light(vec3 position, vec3 normal);
+vec3 ambient(vec3 albedo);
+
+// The light reaching every pixel, but for the point lights, which are added on top by drawing
+// their volumes with deferred_point_light.frag
+void main()
+{
+    vec3 normal = texture(normal_buffer, uv).xyz;
+    output_normal = normal;
+
+    // Nothing was drawn here
+    if (dot(normal, normal) < 0.5) {
+        output_col = sky_color;
+        output_ambient = vec3(0.0);
+        return;
+    }
+
+    vec4 albedo = texture(albedo_buffer, uv);
+    vec3 position = texture(position_buffer, uv).xyz;
+    output_ambient = ambient(albedo.rgb);
+    output_col = vec4(moon_light(position, normal) * albedo.rgb + output_ambient, 1.0);
+}
@@ -0,0 +1,27 @@
+#version 430 core
+
+layout(location = 0) flat in vec4 light_position; // Radius in w
+layout(location = 1) flat in vec3 light_color;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D albedo_buffer;
+uniform sampler2D normal_buffer;
+uniform sampler2D position_buffer;
+
+// From lighting.frag
+vec3 point_light(vec4 light_position, vec3 light_color, vec3 position, vec3 normal);
+
+// One light, added to the pixels its volume covers
+void main()
+{
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    vec3 normal = texelFetch(normal_buffer, pixel, 0).xyz;
+
+    // Nothing was drawn here
+    if (dot(normal, normal) < 0.5) discard;
+
+    vec3 albedo = texelFetch(albedo_buffer, pixel, 0).rgb;
+    vec3 position = texelFetch(position_buffer, pixel, 0).xyz;
+    output_col = vec4(point_light(light_position, light_color, position, normal) * albedo, 0.0);
+}
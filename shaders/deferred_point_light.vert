@@ -0,0 +1,26 @@
+#version 430 core
+
+layout(location = 0) in vec3 input_pos;
+layout(location = 1) in vec4 instance_position; // Radius in w
+layout(location = 2) in vec4 instance_color;
+
+layout(location = 0) flat out vec4 light_position;
+layout(location = 1) flat out vec3 light_color;
+
+
+layout(std140, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec3 camera_position;
+    float time;
+};
+
+
+// A sphere around the light, as big as it reaches
+void main()
+{
+    light_position = instance_position;
+    light_color = instance_color.rgb;
+    gl_Position = view_projection * vec4(instance_position.xyz + input_pos * instance_position.w, 1.0);
+}
@@ -0,0 +1,23 @@
+#version 430 core
+
+layout(location = 1) in vec4 input_col;
+layout(location = 2) in vec3 input_normal;
+layout(location = 3) in vec3 fragNormal;
+layout(location = 4) in vec3 fragPosition;
+layout(location = 5) in vec2 fragUv;
+
+layout(location = 0) out vec4 output_albedo;
+layout(location = 1) out vec3 output_normal;
+layout(location = 2) out vec3 output_position;
+
+// The base colour texture the vertex colours are multiplied with, when `textured`
+uniform sampler2D base_color_texture;
+uniform bool textured;
+
+// Only what the surface is, the lighting comes later
+void main()
+{
+    output_albedo = textured ? input_col * texture(base_color_texture, fragUv) : input_col;
+    output_normal = normalize(fragNormal);
+    output_position = fragPosition;
+}
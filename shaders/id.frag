@@ -0,0 +1,10 @@
+#version 430 core
+
+uniform uint node_id;
+
+layout(location = 0) out uint output_id;
+
+void main()
+{
+    output_id = node_id;
+}
@@ -0,0 +1,10 @@
+#version 430 core
+
+layout(location = 0) in vec3 input_pos;
+
+uniform mat4 mvp_matrix;
+
+void main()
+{
+    gl_Position = mvp_matrix * vec4(input_pos, 1.0);
+}
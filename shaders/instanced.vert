@@ -0,0 +1,36 @@
+#version 430 core
+
+layout(location = 0) in vec3 input_pos;
+layout(location = 1) in vec4 input_col;
+layout(location = 2) in vec3 input_normal;
+layout(location = 3) in mat4 instance_model;
+layout(location = 7) in vec4 instance_tint;
+layout(location = 8) in vec2 input_uv;
+
+layout(location = 1) out vec4 output_col;
+layout(location = 2) out vec3 output_normal;
+layout(location = 3) out vec3 fragNormal;
+layout(location = 4) out vec3 fragPosition;
+layout(location = 5) out vec2 fragUv;
+
+
+layout(std140, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec3 camera_position;
+    float time;
+};
+
+
+void main()
+{
+    gl_Position = view_projection * instance_model * vec4(input_pos, 1.0);
+
+    fragNormal = normalize(mat3(transpose(inverse(instance_model))) * input_normal);
+    fragPosition = vec3(instance_model * vec4(input_pos, 1.0));
+
+    output_col = input_col * instance_tint;
+    output_normal = input_normal;
+    fragUv = input_uv;
+}
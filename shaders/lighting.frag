@@ -0,0 +1,55 @@
+#version 430 core
+
+// The lighting shared by the forward, deferred and transparent shaders, linked into each of them
+// as a shader of its own. Declare what you call before calling it, e.g.:
+//
+//     vec3 shade(vec3 position, vec3 normal, vec3 albedo);
+//     vec3 ambient(vec3 albedo);
+
+#define MAX_LIGHTS 128
+
+layout(std140, binding = 1) uniform Lights {
+    vec4 light_positions[MAX_LIGHTS]; // Radius in w
+    vec4 light_colors[MAX_LIGHTS];
+    int light_count;
+};
+
+// Light from all around, which the ambient occlusion pass takes away from again where it is blocked
+const vec3 ambientLight = vec3(0.12, 0.12, 0.15);
+
+vec3 ambient(vec3 albedo)
+{
+    return ambientLight * albedo;
+}
+
+vec3 moon_light(vec3 position, vec3 normal)
+{
+    vec3 lightPos = vec3(0.0, 60.0, 40.0);
+    vec3 lightColor = vec3(1.0, 1.0, 1.0);
+
+    vec3 lightDir = normalize(lightPos - position);
+    float lambertian = max(0.0, dot(normal, lightDir));
+    return lambertian * lightColor;
+}
+
+// A point light fading out to nothing at its radius, which is in w of its position
+vec3 point_light(vec4 light_position, vec3 light_color, vec3 position, vec3 normal)
+{
+    vec3 to_light = light_position.xyz - position;
+    float distance_to_light = length(to_light);
+    float radius = light_position.w;
+    if (distance_to_light >= radius) return vec3(0.0);
+
+    float falloff = 1.0 - distance_to_light / radius;
+    return max(0.0, dot(normal, to_light / distance_to_light)) * falloff * falloff * light_color;
+}
+
+// The moon light, and whichever point lights are close enough
+vec3 shade(vec3 position, vec3 normal, vec3 albedo)
+{
+    vec3 diffuse = moon_light(position, normal);
+    for (int i = 0; i < light_count; i++) {
+        diffuse += point_light(light_positions[i], light_colors[i].rgb, position, normal);
+    }
+    return diffuse * albedo;
+}
@@ -0,0 +1,32 @@
+#version 430 core
+
+layout(location = 1) in vec4 input_col;
+layout(location = 2) in vec3 input_normal;
+layout(location = 3) in vec3 fragNormal;
+layout(location = 4) in vec3 fragPosition;
+layout(location = 5) in vec2 fragUv;
+
+layout(location = 0) out vec4 output_accumulation;
+layout(location = 1) out float output_revealage;
+
+// The base colour texture the vertex colours are multiplied with, when `textured`
+uniform sampler2D base_color_texture;
+uniform bool textured;
+
+// From lighting.frag
+vec3 shade(vec3 position, vec3 normal, vec3 albedo);
+vec3 ambient(vec3 albedo);
+
+// Lit like the forward shader, but added up with every other transparent layer over the pixel,
+// weighted so that the closer and more opaque layers count for more
+void main()
+{
+    vec4 albedo = textured ? input_col * texture(base_color_texture, fragUv) : input_col;
+    vec3 normalizedNormal = normalize(fragNormal);
+    vec3 color = shade(fragPosition, normalizedNormal, albedo.rgb) + ambient(albedo.rgb);
+    float alpha = albedo.a;
+
+    float weight = clamp(pow(min(1.0, alpha * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0), 1e-2, 3e3);
+    output_accumulation = vec4(color * alpha, alpha) * weight;
+    output_revealage = alpha;
+}
@@ -0,0 +1,18 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D accumulation;
+uniform sampler2D revealage; // How much of the scene still shows through
+
+void main()
+{
+    float revealed = texture(revealage, uv).r;
+    if (revealed >= 1.0) discard; // Nothing transparent here
+
+    vec4 sum = texture(accumulation, uv);
+    vec3 average = sum.rgb / clamp(sum.a, 1e-4, 5e4);
+    output_col = vec4(average, revealed); // Blended with the scene by the revealage
+}
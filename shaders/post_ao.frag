@@ -0,0 +1,18 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+uniform sampler2D ambient;   // The ambient light part of the colour
+uniform sampler2D occlusion;
+uniform float strength;
+
+// Takes away the ambient light that can't reach the pixel
+void main()
+{
+    vec3 color = texture(source, uv).rgb;
+    float blocked = strength * (1.0 - texture(occlusion, uv).r);
+    output_col = vec4(color - blocked * texture(ambient, uv).rgb, 1.0);
+}
@@ -0,0 +1,15 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+uniform sampler2D bloom;
+uniform float strength;
+
+void main()
+{
+    vec3 color = texture(source, uv).rgb + strength * texture(bloom, uv).rgb;
+    output_col = vec4(color, 1.0);
+}
@@ -0,0 +1,23 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+uniform vec2 texel_size;
+uniform vec2 direction; // (1, 0) for horizontal, (0, 1) for vertical
+
+const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+// One direction of a separable 9 tap gaussian blur
+void main()
+{
+    vec3 color = texture(source, uv).rgb * weights[0];
+    for (int i = 1; i < 5; i++) {
+        vec2 offset = direction * texel_size * float(i);
+        color += texture(source, uv + offset).rgb * weights[i];
+        color += texture(source, uv - offset).rgb * weights[i];
+    }
+    output_col = vec4(color, 1.0);
+}
@@ -0,0 +1,17 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+uniform float threshold;
+
+// Keeps only what is brighter than the threshold, fading in softly above it
+void main()
+{
+    vec3 color = texture(source, uv).rgb;
+    float brightness = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    float keep = max(brightness - threshold, 0.0) / max(brightness, 0.0001);
+    output_col = vec4(color * keep, 1.0);
+}
@@ -0,0 +1,12 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+
+void main()
+{
+    output_col = vec4(texture(source, uv).rgb, 1.0);
+}
@@ -0,0 +1,51 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+uniform vec2 texel_size;
+
+const float SPAN_MAX = 8.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float REDUCE_MIN = 1.0 / 128.0;
+
+// Fast approximate anti-aliasing: finds the direction of the edge through each pixel from the
+// luminance around it, and blurs along that edge
+void main()
+{
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+    float luma_nw = dot(texture(source, uv + vec2(-1.0, -1.0) * texel_size).rgb, luma);
+    float luma_ne = dot(texture(source, uv + vec2( 1.0, -1.0) * texel_size).rgb, luma);
+    float luma_sw = dot(texture(source, uv + vec2(-1.0,  1.0) * texel_size).rgb, luma);
+    float luma_se = dot(texture(source, uv + vec2( 1.0,  1.0) * texel_size).rgb, luma);
+    vec3 color_m = texture(source, uv).rgb;
+    float luma_m = dot(color_m, luma);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    vec2 direction = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+         ((luma_nw + luma_sw) - (luma_ne + luma_se))
+    );
+    float reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, -SPAN_MAX, SPAN_MAX) * texel_size;
+
+    vec3 color_a = 0.5 * (
+        texture(source, uv + direction * (1.0 / 3.0 - 0.5)).rgb +
+        texture(source, uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 color_b = color_a * 0.5 + 0.25 * (
+        texture(source, uv - direction * 0.5).rgb +
+        texture(source, uv + direction * 0.5).rgb);
+
+    // The wider blur is only used if it doesn't overshoot what is around the pixel
+    float luma_b = dot(color_b, luma);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        output_col = vec4(color_a, 1.0);
+    } else {
+        output_col = vec4(color_b, 1.0);
+    }
+}
@@ -0,0 +1,14 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+uniform float gamma;
+
+void main()
+{
+    vec3 color = texture(source, uv).rgb;
+    output_col = vec4(pow(max(color, 0.0), vec3(1.0 / gamma)), 1.0);
+}
@@ -0,0 +1,14 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+
+// Shows the world space normals of the scene, mapped from [-1, 1] to colours
+void main()
+{
+    vec3 normal = texture(source, uv).xyz;
+    output_col = vec4(normal * 0.5 + 0.5, 1.0);
+}
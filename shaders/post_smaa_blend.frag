@@ -0,0 +1,32 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+uniform sampler2D weights; // How much to blend across the left (x) and top (y) side of a pixel
+uniform vec2 texel_size;
+
+// Last SMAA pass: blends every pixel with its neighbours across the edges around it
+void main()
+{
+    vec2 own = texture(weights, uv).xy;
+    float left = own.x;
+    float above = own.y;
+    float right = texture(weights, uv + vec2(texel_size.x, 0.0)).x;
+    float below = texture(weights, uv - vec2(0.0, texel_size.y)).y;
+
+    float total = left + above + right + below;
+    vec3 color = texture(source, uv).rgb;
+    if (total > 0.0) {
+        vec3 neighbours =
+            left  * texture(source, uv - vec2(texel_size.x, 0.0)).rgb +
+            right * texture(source, uv + vec2(texel_size.x, 0.0)).rgb +
+            above * texture(source, uv + vec2(0.0, texel_size.y)).rgb +
+            below * texture(source, uv - vec2(0.0, texel_size.y)).rgb;
+        float kept = max(0.0, 1.0 - total);
+        color = (color * kept + neighbours) / (kept + total);
+    }
+    output_col = vec4(color, 1.0);
+}
@@ -0,0 +1,32 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec2 output_edges;
+
+uniform sampler2D source;
+uniform vec2 texel_size;
+
+const float THRESHOLD = 0.1;
+
+float luma(vec2 offset)
+{
+    return dot(texture(source, uv + offset * texel_size).rgb, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// First SMAA pass: is there an edge between this pixel and the one to its left (x), and the
+// one above it (y)?
+void main()
+{
+    float center = luma(vec2(0.0));
+    vec2 delta = abs(center - vec2(luma(vec2(-1.0, 0.0)), luma(vec2(0.0, 1.0))));
+    vec2 edges = step(THRESHOLD, delta);
+    if (edges.x + edges.y == 0.0) {
+        output_edges = vec2(0.0);
+        return;
+    }
+
+    // Local contrast adaptation: an edge right next to a much stronger one is left out
+    float strongest = max(max(delta.x, delta.y), max(abs(center - luma(vec2(1.0, 0.0))), abs(center - luma(vec2(0.0, -1.0)))));
+    output_edges = edges * step(0.5 * strongest, delta);
+}
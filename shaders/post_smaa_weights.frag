@@ -0,0 +1,63 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec2 output_weights;
+
+uniform sampler2D source; // The edges, x for the left side and y for the top side of a pixel
+
+const int MAX_SEARCH = 16;
+
+vec2 edges_at(ivec2 p)
+{
+    return texelFetch(source, clamp(p, ivec2(0), textureSize(source, 0) - 1), 0).xy;
+}
+
+// Coverage of the pixel `position` pixels along an edge of the given length, by the line SMAA
+// would reconstruct from the edges crossing either end of it: a triangle from each crossed end
+// to the middle. MLAA works out the same shapes analytically, as is done here, where SMAA looks
+// them up in a precomputed texture.
+float coverage(float position, float length_, bool start_crossed, bool end_crossed)
+{
+    float half_length = 0.5 * length_;
+    float from_start = start_crossed ? 0.5 * max(0.0, 1.0 - position / half_length) : 0.0;
+    float from_end = end_crossed ? 0.5 * max(0.0, 1.0 - (length_ - position) / half_length) : 0.0;
+    return max(from_start, from_end);
+}
+
+// Second SMAA pass: for each edge through this pixel, finds how far it runs each way and whether
+// other edges cross it at its ends, and from that how much to blend across it
+void main()
+{
+    ivec2 p = ivec2(gl_FragCoord.xy);
+    vec2 edges = edges_at(p);
+    vec2 weights = vec2(0.0);
+
+    // An edge along the top of the pixel, running left and right
+    if (edges.y > 0.5) {
+        int left = 0, right = 0;
+        while (left < MAX_SEARCH && edges_at(p - ivec2(left + 1, 0)).y > 0.5) left++;
+        while (right < MAX_SEARCH && edges_at(p + ivec2(right + 1, 0)).y > 0.5) right++;
+
+        ivec2 start = p - ivec2(left, 0);
+        ivec2 end = p + ivec2(right + 1, 0);
+        bool start_crossed = max(edges_at(start).x, edges_at(start + ivec2(0, 1)).x) > 0.5;
+        bool end_crossed = max(edges_at(end).x, edges_at(end + ivec2(0, 1)).x) > 0.5;
+        weights.y = coverage(float(left) + 0.5, float(left + right + 1), start_crossed, end_crossed);
+    }
+
+    // An edge along the left of the pixel, running down and up
+    if (edges.x > 0.5) {
+        int down = 0, up = 0;
+        while (down < MAX_SEARCH && edges_at(p - ivec2(0, down + 1)).x > 0.5) down++;
+        while (up < MAX_SEARCH && edges_at(p + ivec2(0, up + 1)).x > 0.5) up++;
+
+        ivec2 start = p - ivec2(0, down + 1);
+        ivec2 end = p + ivec2(0, up);
+        bool start_crossed = max(edges_at(start).y, edges_at(start - ivec2(1, 0)).y) > 0.5;
+        bool end_crossed = max(edges_at(end).y, edges_at(end - ivec2(1, 0)).y) > 0.5;
+        weights.x = coverage(float(down) + 0.5, float(down + up + 1), start_crossed, end_crossed);
+    }
+
+    output_weights = weights;
+}
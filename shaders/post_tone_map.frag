@@ -0,0 +1,20 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+uniform float exposure;
+
+// Narkowicz's fit of the ACES filmic curve, from HDR down to [0, 1]
+vec3 aces(vec3 x)
+{
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+void main()
+{
+    vec3 color = texture(source, uv).rgb * exposure;
+    output_col = vec4(aces(color), 1.0);
+}
@@ -0,0 +1,17 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 output_col;
+
+uniform sampler2D source;
+uniform float strength;
+
+// Darkens towards the corners
+void main()
+{
+    vec3 color = texture(source, uv).rgb;
+    float distance_from_center = distance(uv, vec2(0.5));
+    color *= 1.0 - strength * smoothstep(0.3, 0.8, distance_from_center);
+    output_col = vec4(color, 1.0);
+}
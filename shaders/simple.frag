@@ -4,20 +4,25 @@ layout(location = 1) in vec4 input_col;
 layout(location = 2) in vec3 input_normal;
 layout(location = 3) in vec3 fragNormal;
 layout(location = 4) in vec3 fragPosition;
+layout(location = 5) in vec2 fragUv;
 
 layout(location = 0) out vec4 output_col;
 layout(location = 1) out vec3 output_normal;
+layout(location = 2) out vec3 output_ambient;
+
+// The base colour texture the vertex colours are multiplied with, when `textured`
+uniform sampler2D base_color_texture;
+uniform bool textured;
+
+// From lighting.frag
+vec3 shade(vec3 position, vec3 normal, vec3 albedo);
+vec3 ambient(vec3 albedo);
 
 void main()
 {
-    vec3 lightPos = vec3(0.0, 60.0, 40.0);
-    vec3 lightColor = vec3(1.0, 1.0, 1.0);
-
+    vec4 albedo = textured ? input_col * texture(base_color_texture, fragUv) : input_col;
     vec3 normalizedNormal = normalize(fragNormal);
-
-    vec3 lightDir = normalize(lightPos - fragPosition);
-    float lambertian = max(0.0, dot(normalizedNormal, lightDir));
-
-    vec3 diffuse = lambertian * lightColor;
-    output_col = vec4(diffuse, 1.0) * input_col;
+    output_ambient = ambient(albedo.rgb);
+    output_col = vec4(shade(fragPosition, normalizedNormal, albedo.rgb) + output_ambient, albedo.a);
+    output_normal = normalizedNormal; // For the post-processing passes
 }
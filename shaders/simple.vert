@@ -3,24 +3,35 @@
 layout(location = 0) in vec3 input_pos;
 layout(location = 1) in vec4 input_col;
 layout(location = 2) in vec3 input_normal;
+layout(location = 8) in vec2 input_uv;
 
 layout(location = 1) out vec4 output_col;
 layout(location = 2) out vec3 output_normal;
 layout(location = 3) out vec3 fragNormal;
 layout(location = 4) out vec3 fragPosition;
+layout(location = 5) out vec2 fragUv;
 
 
-uniform mat4 mvp_matrix;
+layout(std140, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec3 camera_position;
+    float time;
+};
+
 uniform mat4 model_matrix;
+uniform vec4 tint;
 
 
 void main()
 {
-    gl_Position = mvp_matrix * vec4(input_pos, 1.0);
+    gl_Position = view_projection * model_matrix * vec4(input_pos, 1.0);
 
     fragNormal = normalize(mat3(transpose(inverse(model_matrix))) * input_normal);
     fragPosition = vec3(model_matrix * vec4(input_pos, 1.0));
 
-    output_col = input_col;
+    output_col = input_col * tint;
     output_normal = input_normal;
+    fragUv = input_uv;
 }
\ No newline at end of file
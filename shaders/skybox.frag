@@ -0,0 +1,16 @@
+#version 430 core
+
+layout(location = 0) in vec3 direction;
+
+layout(location = 0) out vec4 output_col;
+layout(location = 1) out vec3 output_normal;
+layout(location = 2) out vec3 output_ambient;
+
+uniform samplerCube cubemap;
+
+void main()
+{
+    output_col = vec4(texture(cubemap, direction).rgb, 1.0);
+    output_normal = vec3(0.0);
+    output_ambient = vec3(0.0);
+}
@@ -0,0 +1,22 @@
+#version 430 core
+
+layout(location = 0) in vec3 input_pos;
+
+layout(location = 0) out vec3 direction;
+
+layout(std140, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec3 camera_position;
+    float time;
+};
+
+void main()
+{
+    direction = input_pos;
+
+    // Only the rotation of the camera, so the sky never gets any closer
+    vec4 position = projection * mat4(mat3(view)) * vec4(input_pos, 1.0);
+    gl_Position = position.xyww; // On the far plane
+}
@@ -0,0 +1,64 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out float output_occlusion;
+
+layout(std140, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec3 camera_position;
+    float time;
+};
+
+#define MAX_KERNEL_SIZE 64
+
+uniform sampler2D depth_buffer;
+uniform sampler2D normal_buffer;  // World space
+uniform sampler2D noise_texture;
+uniform mat4 inverse_projection;
+uniform vec3 samples[MAX_KERNEL_SIZE];
+uniform int kernel_size;
+uniform float radius;
+uniform float bias;
+uniform vec2 noise_scale;         // How many times the noise tiles across the screen
+
+vec3 view_position(vec2 at)
+{
+    float depth = texture(depth_buffer, at).r;
+    vec4 position = inverse_projection * vec4(vec3(at, depth) * 2.0 - 1.0, 1.0);
+    return position.xyz / position.w;
+}
+
+void main()
+{
+    // Nothing was drawn here, so nothing to occlude
+    if (texture(depth_buffer, uv).r >= 1.0) {
+        output_occlusion = 1.0;
+        return;
+    }
+
+    vec3 position = view_position(uv);
+    vec3 normal = normalize(mat3(view) * texture(normal_buffer, uv).xyz);
+
+    // A basis around the normal, turned by the noise
+    vec3 random = vec3(texture(noise_texture, uv * noise_scale).xy, 0.0);
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < kernel_size; i++) {
+        vec3 sample_position = position + tbn * samples[i] * radius;
+
+        vec4 projected = projection * vec4(sample_position, 1.0);
+        vec2 sample_uv = projected.xy / projected.w * 0.5 + 0.5;
+        float surface_depth = view_position(sample_uv).z;
+
+        // Whatever is far in front of the pixel doesn't count, or silhouettes would get halos
+        float in_range = smoothstep(0.0, 1.0, radius / abs(position.z - surface_depth));
+        occlusion += (surface_depth >= sample_position.z + bias ? 1.0 : 0.0) * in_range;
+    }
+    output_occlusion = 1.0 - occlusion / float(kernel_size);
+}
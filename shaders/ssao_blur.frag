@@ -0,0 +1,22 @@
+#version 430 core
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out float output_occlusion;
+
+uniform sampler2D source;
+uniform int blur_radius;
+
+// Box blur, wide enough to hide the tiling of the noise
+void main()
+{
+    vec2 texel_size = 1.0 / vec2(textureSize(source, 0));
+    float sum = 0.0;
+    for (int x = -blur_radius; x <= blur_radius; x++) {
+        for (int y = -blur_radius; y <= blur_radius; y++) {
+            sum += texture(source, uv + vec2(x, y) * texel_size).r;
+        }
+    }
+    float side = float(2 * blur_radius + 1);
+    output_occlusion = sum / (side * side);
+}
@@ -0,0 +1,80 @@
+#version 430 core
+
+layout(location = 0) in vec3 direction;
+
+layout(location = 0) out vec4 output_col;
+layout(location = 1) out vec3 output_normal;
+layout(location = 2) out vec3 output_ambient;
+
+layout(std140, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec3 camera_position;
+    float time;
+};
+
+const vec3 nightSky = vec3(0.035, 0.046, 0.078);
+const vec3 earthDirection = vec3(0.35, 0.3, -0.89);
+const float earthRadius = 0.09; // Angular, in radians
+const vec3 sunDirection = vec3(-0.8, 0.2, 0.55);
+
+float hash(vec3 p)
+{
+    p = fract(p * vec3(443.897, 441.423, 437.195));
+    p += dot(p, p.yzx + 19.19);
+    return fract((p.x + p.y) * p.z);
+}
+
+// Stars on a grid of cells around the sky, at most one per cell, twinkling slightly
+vec3 stars(vec3 d)
+{
+    vec3 cell = floor(d * 250.0);
+    float h = hash(cell);
+    if (h < 0.996) return vec3(0.0);
+
+    vec3 center = (cell + 0.5 + 0.3 * (vec3(hash(cell + 1.0), hash(cell + 2.0), hash(cell + 3.0)) - 0.5)) / 250.0;
+    float size = 1.0 - smoothstep(0.0, 0.0025, length(d - normalize(center)));
+    float twinkle = 0.75 + 0.25 * sin(time * (1.0 + 3.0 * hash(cell + 4.0)) + 40.0 * h);
+    vec3 tint = mix(vec3(0.7, 0.8, 1.0), vec3(1.0, 0.85, 0.7), hash(cell + 5.0));
+    return tint * size * twinkle * (1.0 + 3.0 * (h - 0.996) / 0.004);
+}
+
+// The Earth as seen from the moon: blue oceans, some land and clouds, lit from one side
+vec3 earth(vec3 d, inout float coverage)
+{
+    vec3 center = normalize(earthDirection);
+    float angle = acos(clamp(dot(d, center), -1.0, 1.0));
+    coverage = 1.0 - smoothstep(earthRadius - 0.002, earthRadius, angle);
+    if (coverage <= 0.0) return vec3(0.0);
+
+    // Where on the disc the direction is, turned into a point on a sphere facing us
+    vec3 right = normalize(cross(center, vec3(0.0, 1.0, 0.0)));
+    vec3 up = cross(right, center);
+    vec2 disc = vec2(dot(d, right), dot(d, up)) / sin(earthRadius);
+    vec3 surface = vec3(disc, sqrt(max(0.0, 1.0 - dot(disc, disc))));
+
+    vec3 normal = surface.x * right + surface.y * up - surface.z * center;
+    float daylight = smoothstep(-0.1, 0.2, dot(normal, normalize(sunDirection)));
+
+    float land = step(0.55, hash(floor(surface * 6.0)) * 0.5 + 0.5 * sin(surface.x * 9.0 + surface.y * 5.0));
+    float clouds = smoothstep(0.6, 0.9, 0.5 + 0.5 * sin(surface.x * 21.0 + sin(surface.y * 13.0) * 2.0));
+    vec3 color = mix(vec3(0.05, 0.15, 0.5), vec3(0.15, 0.35, 0.12), land);
+    color = mix(color, vec3(0.9), clouds);
+
+    // The atmosphere glows towards the rim
+    color += vec3(0.2, 0.4, 0.9) * pow(1.0 - surface.z, 3.0);
+    return color * daylight * 1.5;
+}
+
+void main()
+{
+    vec3 d = normalize(direction);
+    float coverage = 0.0;
+    vec3 planet = earth(d, coverage);
+    vec3 color = mix(nightSky + stars(d), planet, coverage);
+
+    output_col = vec4(color, 1.0);
+    output_normal = vec3(0.0);
+    output_ambient = vec3(0.0);
+}
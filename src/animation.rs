@@ -0,0 +1,293 @@
+extern crate nalgebra_glm as glm;
+
+use serde::{Serialize, Deserialize};
+
+use crate::scene_graph::SceneNode;
+
+// Keyframe animation of scene nodes.
+//
+// An `Animation` is a set of tracks, each of which drives a single property (position, rotation
+// or scale) of a single `SceneNode` through a list of keyframes. The `AnimationPlayer` keeps
+// track of the playing animations, advances their clocks every frame and writes the sampled
+// values back into the targeted nodes.
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Interpolation {
+    Step,   // Hold the value of the previous keyframe
+    Linear, // Straight line between keyframes
+    Cubic,  // Catmull-Rom spline through the keyframes
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LoopMode {
+    Once,     // Play to the end and stay there
+    Loop,     // Jump back to the start when reaching the end
+    PingPong, // Play forwards, then backwards, then forwards...
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Property {
+    Position,
+    Rotation,    // Euler angles, see `SceneNode::set_euler_angles`
+    Scale,
+    Orientation, // Rotation vectors (axis times angle), interpolated along the shortest arc
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time  : f32,        // measured in seconds
+    pub value : glm::Vec3,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, value: glm::Vec3) -> Self {
+        Keyframe { time, value }
+    }
+}
+
+pub struct Track {
+    pub target        : *mut SceneNode,  // The node I animate
+    pub property      : Property,        // Which of its transforms I animate
+    pub interpolation : Interpolation,   // How I get from one keyframe to the next
+    pub keyframes     : Vec<Keyframe>,   // Sorted by time
+}
+
+impl Track {
+    pub fn new(target: &SceneNode, property: Property, interpolation: Interpolation) -> Self {
+        Track {
+            target: target as *const SceneNode as *mut SceneNode,
+            property,
+            interpolation,
+            keyframes: vec![],
+        }
+    }
+
+    pub fn with_keyframe(mut self, time: f32, value: glm::Vec3) -> Self {
+        self.add_keyframe(time, value);
+        self
+    }
+
+    // Inserts the keyframe while keeping the keyframes sorted by time
+    pub fn add_keyframe(&mut self, time: f32, value: glm::Vec3) {
+        let i = self.keyframes.iter().position(|k| k.time > time).unwrap_or(self.keyframes.len());
+        self.keyframes.insert(i, Keyframe::new(time, value));
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    pub fn sample(&self, time: f32) -> Option<glm::Vec3> {
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        let last = keys.last()?;
+        if time <= first.time { return Some(first.value) }
+        if time >= last.time  { return Some(last.value) }
+
+        // Index of the keyframe ending the segment we are in
+        let i = keys.iter().position(|k| k.time > time).unwrap();
+        let (k1, k2) = (&keys[i - 1], &keys[i]);
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 { (time - k1.time) / span } else { 1.0 };
+
+        Some(match self.interpolation {
+            Interpolation::Step => k1.value,
+            Interpolation::Linear => glm::lerp(&k1.value, &k2.value, t),
+            Interpolation::Cubic => {
+                // Clamp the outer control points at the ends of the track
+                let k0 = if i >= 2 { &keys[i - 2] } else { k1 };
+                let k3 = if i + 1 < keys.len() { &keys[i + 1] } else { k2 };
+
+                // Tangents scaled to the length of this segment, so uneven keyframe spacing
+                // doesn't make the curve overshoot
+                let m1 = tangent(k0, k2) * span;
+                let m2 = tangent(k1, k3) * span;
+                hermite(&k1.value, &m1, &k2.value, &m2, t)
+            }
+        })
+    }
+
+    fn apply(&self, time: f32) {
+        if let Some(value) = self.sample(time) {
+            let node = unsafe { &mut *self.target };
+            match self.property {
+                Property::Position    => node.position = value,
+                Property::Rotation    => node.set_euler_angles(value),
+                Property::Scale       => node.scale    = value,
+                Property::Orientation => node.rotation = self.sample_orientation(time).unwrap(),
+            }
+        }
+    }
+
+    // Orientation tracks can't be interpolated component-wise like the rest, so go between the
+    // keyframes along the shortest arc instead. Cubic interpolation falls back to that too.
+    fn sample_orientation(&self, time: f32) -> Option<glm::Quat> {
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        let last = keys.last()?;
+        if time <= first.time { return Some(quat_from_rotation_vector(&first.value)) }
+        if time >= last.time  { return Some(quat_from_rotation_vector(&last.value)) }
+
+        let i = keys.iter().position(|k| k.time > time).unwrap();
+        let (k1, k2) = (&keys[i - 1], &keys[i]);
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 { (time - k1.time) / span } else { 1.0 };
+
+        let (q1, q2) = (quat_from_rotation_vector(&k1.value), quat_from_rotation_vector(&k2.value));
+        Some(match self.interpolation {
+            Interpolation::Step => q1,
+            _ => glm::quat_slerp(&q1, &q2, t),
+        })
+    }
+}
+
+// The axis of the rotation scaled by its angle
+pub fn rotation_vector(rotation: &glm::Quat) -> glm::Vec3 {
+    let axis = glm::vec3(rotation.i, rotation.j, rotation.k);
+    let s = glm::length(&axis);
+    if s < 1e-9 { return glm::zero() }
+    axis / s * 2.0 * s.atan2(rotation.w)
+}
+
+pub fn quat_from_rotation_vector(vector: &glm::Vec3) -> glm::Quat {
+    let angle = glm::length(vector);
+    if angle < 1e-9 { return glm::quat_identity() }
+    glm::quat_angle_axis(angle, &(vector / angle))
+}
+
+fn tangent(from: &Keyframe, to: &Keyframe) -> glm::Vec3 {
+    let dt = to.time - from.time;
+    if dt > 0.0 { (to.value - from.value) / dt } else { glm::zero() }
+}
+
+fn hermite(p1: &glm::Vec3, m1: &glm::Vec3, p2: &glm::Vec3, m2: &glm::Vec3, t: f32) -> glm::Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p1 * (2.0 * t3 - 3.0 * t2 + 1.0)
+        + m1 * (t3 - 2.0 * t2 + t)
+        + p2 * (-2.0 * t3 + 3.0 * t2)
+        + m2 * (t3 - t2)
+}
+
+pub struct Animation {
+    pub name      : String,
+    pub tracks    : Vec<Track>,
+    pub loop_mode : LoopMode,
+}
+
+impl Animation {
+    pub fn new(name: &str, loop_mode: LoopMode) -> Self {
+        Animation {
+            name: name.to_string(),
+            tracks: vec![],
+            loop_mode,
+        }
+    }
+
+    pub fn with_track(mut self, track: Track) -> Self {
+        self.tracks.push(track);
+        self
+    }
+
+    // The length of the longest track
+    pub fn duration(&self) -> f32 {
+        self.tracks.iter().map(|t| t.duration()).fold(0.0, f32::max)
+    }
+
+    // Maps time since the animation started onto the timeline of the tracks
+    pub fn local_time(&self, time: f32) -> f32 {
+        let duration = self.duration();
+        if duration <= 0.0 { return 0.0 }
+        match self.loop_mode {
+            LoopMode::Once => time.min(duration),
+            LoopMode::Loop => time.rem_euclid(duration),
+            LoopMode::PingPong => {
+                let t = time.rem_euclid(2.0 * duration);
+                if t > duration { 2.0 * duration - t } else { t }
+            }
+        }
+    }
+
+    pub fn is_finished(&self, time: f32) -> bool {
+        self.loop_mode == LoopMode::Once && time >= self.duration()
+    }
+
+    pub fn apply(&self, time: f32) {
+        let local_time = self.local_time(time);
+        for track in &self.tracks {
+            track.apply(local_time);
+        }
+    }
+}
+
+struct Playback {
+    animation : Animation,
+    time      : f32,
+    speed     : f32,
+    playing   : bool,
+}
+
+pub struct AnimationPlayer {
+    playbacks: Vec<Playback>,
+}
+
+impl AnimationPlayer {
+    pub fn new() -> Self {
+        AnimationPlayer { playbacks: vec![] }
+    }
+
+    // Starts playing the animation, returning a handle to control it with
+    pub fn play(&mut self, animation: Animation) -> usize {
+        self.playbacks.push(Playback {
+            animation,
+            time: 0.0,
+            speed: 1.0,
+            playing: true,
+        });
+        self.playbacks.len() - 1
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.playbacks.iter().position(|p| p.animation.name == name)
+    }
+
+    pub fn pause(&mut self, handle: usize) {
+        self.playbacks[handle].playing = false;
+    }
+
+    pub fn resume(&mut self, handle: usize) {
+        self.playbacks[handle].playing = true;
+    }
+
+    pub fn toggle(&mut self, handle: usize) {
+        let playback = &mut self.playbacks[handle];
+        playback.playing = !playback.playing;
+    }
+
+    pub fn restart(&mut self, handle: usize) {
+        let playback = &mut self.playbacks[handle];
+        playback.time = 0.0;
+        playback.playing = true;
+    }
+
+    pub fn is_playing(&self, handle: usize) -> bool {
+        self.playbacks[handle].playing
+    }
+
+    pub fn set_speed(&mut self, handle: usize, speed: f32) {
+        self.playbacks[handle].speed = speed;
+    }
+
+    // Advances every playing animation and writes the result into the targeted nodes.
+    // Call this once per frame, before drawing the scene.
+    pub fn update(&mut self, delta_time: f32) {
+        for playback in self.playbacks.iter_mut() {
+            if !playback.playing { continue }
+            playback.time += delta_time * playback.speed;
+            playback.animation.apply(playback.time);
+            if playback.animation.is_finished(playback.time) {
+                playback.playing = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+extern crate nalgebra_glm as glm;
+
+// Bounding volumes and view frustum tests, used to skip drawing what the camera can't see.
+
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingSphere {
+    pub center: glm::Vec3,
+    pub radius: f32,
+}
+
+// Both kinds of bounds around the same geometry. The sphere is the cheaper test, the box the
+// tighter one.
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    pub aabb   : Aabb,
+    pub sphere : BoundingSphere,
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        Aabb {
+            min: glm::vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: glm::vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    // Around a flat list of [x, y, z, x, y, z, ...] positions
+    pub fn from_positions(positions: &[f32]) -> Self {
+        let mut aabb = Aabb::empty();
+        for p in positions.chunks(3) {
+            aabb.grow(&glm::vec3(p[0], p[1], p[2]));
+        }
+        aabb
+    }
+
+    pub fn grow(&mut self, point: &glm::Vec3) {
+        self.min = glm::min2(&self.min, point);
+        self.max = glm::max2(&self.max, point);
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: glm::min2(&self.min, &other.min),
+            max: glm::max2(&self.max, &other.max),
+        }
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extents(&self) -> glm::Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn corners(&self) -> [glm::Vec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            glm::vec3(a.x, a.y, a.z), glm::vec3(b.x, a.y, a.z),
+            glm::vec3(a.x, b.y, a.z), glm::vec3(b.x, b.y, a.z),
+            glm::vec3(a.x, a.y, b.z), glm::vec3(b.x, a.y, b.z),
+            glm::vec3(a.x, b.y, b.z), glm::vec3(b.x, b.y, b.z),
+        ]
+    }
+
+    // The box around this box after being transformed (Arvo's method)
+    pub fn transformed(&self, matrix: &glm::Mat4) -> Aabb {
+        let center = self.center();
+        let extents = self.extents();
+        let new_center = glm::vec4_to_vec3(&(matrix * glm::vec4(center.x, center.y, center.z, 1.0)));
+        let mut new_extents = glm::Vec3::zeros();
+        for i in 0..3 {
+            for j in 0..3 {
+                new_extents[i] += matrix[(i, j)].abs() * extents[j];
+            }
+        }
+        Aabb { min: new_center - new_extents, max: new_center + new_extents }
+    }
+
+    // Slab test, returning the distance along the ray to where it enters the box
+    pub fn intersect_ray(&self, origin: &glm::Vec3, direction: &glm::Vec3) -> Option<f32> {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::INFINITY;
+        for i in 0..3 {
+            if direction[i].abs() < 1e-9 {
+                if origin[i] < self.min[i] || origin[i] > self.max[i] { return None }
+            } else {
+                let t0 = (self.min[i] - origin[i]) / direction[i];
+                let t1 = (self.max[i] - origin[i]) / direction[i];
+                t_min = t_min.max(t0.min(t1));
+                t_max = t_max.min(t0.max(t1));
+            }
+        }
+        if t_min <= t_max { Some(t_min) } else { None }
+    }
+}
+
+impl BoundingSphere {
+    // Centered on the box around the positions, just large enough to reach the farthest one
+    pub fn from_positions(positions: &[f32]) -> Self {
+        let center = Aabb::from_positions(positions).center();
+        let radius = positions.chunks(3)
+            .map(|p| glm::distance(&center, &glm::vec3(p[0], p[1], p[2])))
+            .fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    pub fn transformed(&self, matrix: &glm::Mat4) -> BoundingSphere {
+        let c = self.center;
+        let center = glm::vec4_to_vec3(&(matrix * glm::vec4(c.x, c.y, c.z, 1.0)));
+
+        // Grow the radius by the largest scaling along any axis
+        let scale = (0..3)
+            .map(|j| glm::length(&glm::vec3(matrix[(0, j)], matrix[(1, j)], matrix[(2, j)])))
+            .fold(0.0, f32::max);
+        BoundingSphere { center, radius: self.radius * scale }
+    }
+}
+
+impl Bounds {
+    pub fn from_positions(positions: &[f32]) -> Self {
+        Bounds {
+            aabb: Aabb::from_positions(positions),
+            sphere: BoundingSphere::from_positions(positions),
+        }
+    }
+
+    pub fn transformed(&self, matrix: &glm::Mat4) -> Bounds {
+        Bounds {
+            aabb: self.aabb.transformed(matrix),
+            sphere: self.sphere.transformed(matrix),
+        }
+    }
+}
+
+// The six planes enclosing what a camera can see, with normals pointing inwards
+pub struct Frustum {
+    planes: [glm::Vec4; 6],
+}
+
+impl Frustum {
+    // Extracts the planes from a `perspective * view` matrix (Gribb & Hartmann). Everything
+    // tested against the frustum must then be given in world space.
+    pub fn from_matrix(view_projection: &glm::Mat4) -> Self {
+        let row = |i: usize| glm::row(view_projection, i);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        let mut planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2];
+        for plane in planes.iter_mut() {
+            let length = glm::length(&glm::vec4_to_vec3(plane));
+            if length > 0.0 {
+                *plane /= length;
+            }
+        }
+        Frustum { planes }
+    }
+
+    fn distance(plane: &glm::Vec4, point: &glm::Vec3) -> f32 {
+        plane.x * point.x + plane.y * point.y + plane.z * point.z + plane.w
+    }
+
+    pub fn contains_point(&self, point: &glm::Vec3) -> bool {
+        self.planes.iter().all(|plane| Frustum::distance(plane, point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| Frustum::distance(plane, &sphere.center) >= -sphere.radius)
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal
+            let corner = glm::vec3(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            Frustum::distance(plane, &corner) >= 0.0
+        })
+    }
+
+    // Sphere first, since it's cheaper and rejects most of what is far outside
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.intersects_sphere(&bounds.sphere) && self.intersects_aabb(&bounds.aabb)
+    }
+}
+
+// How many nodes were drawn and how many were skipped for being out of view
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CullingStats {
+    pub drawn      : u32,
+    pub culled     : u32,
+    pub draw_calls : u32, // Fewer than drawn when instances are batched together
+}
@@ -0,0 +1,222 @@
+extern crate nalgebra_glm as glm;
+
+use crate::mesh::Mesh;
+
+// Spatial queries against triangle meshes.
+//
+// `TriangleGrid` buckets the triangles of a mesh into a uniform grid over the XZ plane, which
+// suits height fields like the lunar terrain well: a height query only has to look at the
+// triangles of a single cell, and a ray only visits the cells it passes over.
+
+const EPSILON: f32 = 1e-6;
+
+// Roughly how many triangles we aim to have in each cell
+const TRIANGLES_PER_CELL: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub distance : f32,       // Along the normalized ray direction
+    pub point    : glm::Vec3,
+    pub normal   : glm::Vec3, // Geometric normal of the triangle that was hit
+    pub triangle : usize,     // Index of that triangle, i.e. indices[3*triangle..3*triangle+3]
+}
+
+// Möller-Trumbore ray/triangle intersection, hitting both sides of the triangle.
+// Returns the distance along `direction`, in multiples of its length.
+pub fn ray_triangle(origin: &glm::Vec3, direction: &glm::Vec3, triangle: &[glm::Vec3; 3]) -> Option<f32> {
+    let edge1 = triangle[1] - triangle[0];
+    let edge2 = triangle[2] - triangle[0];
+    let p = glm::cross(direction, &edge2);
+    let det = glm::dot(&edge1, &p);
+    if det.abs() < EPSILON { return None }
+
+    let inv_det = 1.0 / det;
+    let s = origin - triangle[0];
+    let u = glm::dot(&s, &p) * inv_det;
+    if !(0.0..=1.0).contains(&u) { return None }
+
+    let q = glm::cross(&s, &edge1);
+    let v = glm::dot(direction, &q) * inv_det;
+    if v < 0.0 || u + v > 1.0 { return None }
+
+    let t = glm::dot(&edge2, &q) * inv_det;
+    if t >= 0.0 { Some(t) } else { None }
+}
+
+pub fn triangle_normal(triangle: &[glm::Vec3; 3]) -> glm::Vec3 {
+    glm::normalize(&glm::cross(&(triangle[1] - triangle[0]), &(triangle[2] - triangle[0])))
+}
+
+// Reads triangle `i` of the mesh
+pub fn mesh_triangle(mesh: &Mesh, i: usize) -> [glm::Vec3; 3] {
+    let vertex = |j: usize| {
+        let k = mesh.indices[3 * i + j] as usize * 3;
+        glm::vec3(mesh.vertices[k], mesh.vertices[k + 1], mesh.vertices[k + 2])
+    };
+    [vertex(0), vertex(1), vertex(2)]
+}
+
+pub struct TriangleGrid {
+    triangles : Vec<[glm::Vec3; 3]>,
+    cells     : Vec<Vec<u32>>, // Triangle indices overlapping each cell, row by row
+    min       : glm::Vec2,     // Corner of the grid in the XZ plane
+    max       : glm::Vec2,
+    cell_size : f32,
+    columns   : usize,         // Cells along X
+    rows      : usize,         // Cells along Z
+}
+
+impl TriangleGrid {
+    pub fn new(mesh: &Mesh) -> Self {
+        let triangles: Vec<[glm::Vec3; 3]> = (0..mesh.indices.len() / 3)
+            .map(|i| mesh_triangle(mesh, i))
+            .collect();
+
+        let mut min = glm::vec2(f32::INFINITY, f32::INFINITY);
+        let mut max = glm::vec2(f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for vertex in triangles.iter().flatten() {
+            min = glm::min2(&min, &glm::vec2(vertex.x, vertex.z));
+            max = glm::max2(&max, &glm::vec2(vertex.x, vertex.z));
+        }
+        if triangles.is_empty() {
+            min = glm::zero();
+            max = glm::zero();
+        }
+
+        // Square cells, sized so the triangles spread out to about TRIANGLES_PER_CELL each
+        let extent = max - min;
+        let area = (extent.x * extent.y).max(EPSILON);
+        let cell_count = (triangles.len() as f32 / TRIANGLES_PER_CELL).max(1.0);
+        let cell_size = (area / cell_count).sqrt().max(EPSILON);
+        let columns = ((extent.x / cell_size).ceil() as usize).max(1);
+        let rows = ((extent.y / cell_size).ceil() as usize).max(1);
+
+        let mut grid = TriangleGrid {
+            triangles: vec![],
+            cells: vec![vec![]; columns * rows],
+            min,
+            max,
+            cell_size,
+            columns,
+            rows,
+        };
+
+        for (i, triangle) in triangles.iter().enumerate() {
+            let lo = triangle.iter().fold(glm::vec2(f32::INFINITY, f32::INFINITY), |m, v| glm::min2(&m, &glm::vec2(v.x, v.z)));
+            let hi = triangle.iter().fold(glm::vec2(f32::NEG_INFINITY, f32::NEG_INFINITY), |m, v| glm::max2(&m, &glm::vec2(v.x, v.z)));
+            for row in grid.row(lo.y)..=grid.row(hi.y) {
+                for column in grid.column(lo.x)..=grid.column(hi.x) {
+                    grid.cells[row * columns + column].push(i as u32);
+                }
+            }
+        }
+        grid.triangles = triangles;
+        grid
+    }
+
+    fn column(&self, x: f32) -> usize {
+        (((x - self.min.x) / self.cell_size).floor().max(0.0) as usize).min(self.columns - 1)
+    }
+
+    fn row(&self, z: f32) -> usize {
+        (((z - self.min.y) / self.cell_size).floor().max(0.0) as usize).min(self.rows - 1)
+    }
+
+    pub fn contains(&self, x: f32, z: f32) -> bool {
+        x >= self.min.x && x <= self.max.x && z >= self.min.y && z <= self.max.y
+    }
+
+    // The height of the highest surface right above or below the point, if there is any
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        if !self.contains(x, z) { return None }
+
+        let origin = glm::vec3(x, 0.0, z);
+        let down = glm::vec3(0.0, -1.0, 0.0);
+        let cell = &self.cells[self.row(z) * self.columns + self.column(x)];
+        cell.iter()
+            .filter_map(|&i| {
+                // Cast from the plane y = 0 both ways, measuring signed distance upwards
+                let triangle = &self.triangles[i as usize];
+                ray_triangle(&origin, &down, triangle).map(|t| -t)
+                    .or_else(|| ray_triangle(&origin, &-down, triangle))
+            })
+            .fold(None, |highest: Option<f32>, y| Some(highest.map_or(y, |h| h.max(y))))
+    }
+
+    // The nearest triangle along the ray, if any
+    pub fn raycast(&self, origin: &glm::Vec3, direction: &glm::Vec3) -> Option<RayHit> {
+        if glm::length(direction) < EPSILON { return None }
+        let direction = glm::normalize(direction);
+        let (t_start, t_end) = self.clip(origin, &direction)?;
+
+        // Walk the cells the ray passes over, in order (Amanatides & Woo)
+        let start = origin + direction * t_start;
+        let mut column = self.column(start.x) as isize;
+        let mut row = self.row(start.z) as isize;
+
+        let step_column = if direction.x > 0.0 { 1 } else { -1 };
+        let step_row = if direction.z > 0.0 { 1 } else { -1 };
+        let boundary = |cell: isize, step: isize, min: f32| min + (cell + if step > 0 { 1 } else { 0 }) as f32 * self.cell_size;
+
+        let (mut t_next_column, t_delta_column) = if direction.x.abs() > EPSILON {
+            ((boundary(column, step_column, self.min.x) - origin.x) / direction.x, self.cell_size / direction.x.abs())
+        } else {
+            (f32::INFINITY, f32::INFINITY)
+        };
+        let (mut t_next_row, t_delta_row) = if direction.z.abs() > EPSILON {
+            ((boundary(row, step_row, self.min.y) - origin.z) / direction.z, self.cell_size / direction.z.abs())
+        } else {
+            (f32::INFINITY, f32::INFINITY)
+        };
+
+        loop {
+            let t_cell_exit = t_next_column.min(t_next_row).min(t_end);
+
+            // Triangles can span several cells, so only accept hits within this one
+            let mut nearest: Option<(f32, usize)> = None;
+            for &i in &self.cells[row as usize * self.columns + column as usize] {
+                if let Some(t) = ray_triangle(origin, &direction, &self.triangles[i as usize]) {
+                    if t <= t_cell_exit + EPSILON && nearest.is_none_or(|(best, _)| t < best) {
+                        nearest = Some((t, i as usize));
+                    }
+                }
+            }
+            if let Some((distance, triangle)) = nearest {
+                return Some(RayHit {
+                    distance,
+                    point: origin + direction * distance,
+                    normal: triangle_normal(&self.triangles[triangle]),
+                    triangle,
+                });
+            }
+
+            if t_cell_exit >= t_end { return None }
+            if t_next_column < t_next_row {
+                column += step_column;
+                t_next_column += t_delta_column;
+            } else {
+                row += step_row;
+                t_next_row += t_delta_row;
+            }
+            if column < 0 || row < 0 || column >= self.columns as isize || row >= self.rows as isize {
+                return None
+            }
+        }
+    }
+
+    // The range of distances along the ray where it is above or below the grid
+    fn clip(&self, origin: &glm::Vec3, direction: &glm::Vec3) -> Option<(f32, f32)> {
+        let mut t_min = 0.0f32;
+        let mut t_max = f32::INFINITY;
+        for &(o, d, lo, hi) in &[(origin.x, direction.x, self.min.x, self.max.x), (origin.z, direction.z, self.min.y, self.max.y)] {
+            if d.abs() < EPSILON {
+                if o < lo || o > hi { return None }
+            } else {
+                let (t0, t1) = ((lo - o) / d, (hi - o) / d);
+                t_min = t_min.max(t0.min(t1));
+                t_max = t_max.min(t0.max(t1));
+            }
+        }
+        if t_min <= t_max { Some((t_min, t_max)) } else { None }
+    }
+}
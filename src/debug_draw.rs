@@ -0,0 +1,274 @@
+extern crate nalgebra_glm as glm;
+
+use std::sync::Mutex;
+use std::{mem, ptr};
+
+use crate::bounds::Aabb;
+use crate::framebuffer::Framebuffer;
+use crate::shader::{Shader, ShaderBuilder};
+
+// Lines, arrows, points, grids, spheres and text anchors drawn from anywhere, without building
+// meshes for them:
+//
+//     debug_draw::arrow(position, position + heading, Style::new(glm::vec4(1.0, 0.0, 0.0, 1.0)));
+//     debug_draw::point(hit.point, 8.0, Style::new(white).on_top().lasting(2.0));
+//
+// Everything asked for is queued up, and `DebugDraw::draw` draws it all over the scene in the
+// end, from a vertex buffer rebuilt every frame. Shapes are drawn for a single frame unless given
+// a lifetime in seconds, and hide behind the scene unless drawn on top.
+//
+// There is no text rendering, so a text anchor is drawn as a small marker, and its label and
+// where it ends up on screen can be had from `DebugDraw::labels` for whatever wants to show them.
+
+#[derive(Clone, Copy, Debug)]
+pub struct Style {
+    pub color      : glm::Vec4,
+    pub depth_test : bool, // Hidden behind what is closer, rather than drawn over everything
+    pub lifetime   : f32,  // In seconds, 0 for a single frame
+}
+
+impl Style {
+    pub fn new(color: glm::Vec4) -> Self {
+        Style { color, depth_test: true, lifetime: 0.0 }
+    }
+
+    pub fn on_top(mut self) -> Self {
+        self.depth_test = false;
+        self
+    }
+
+    pub fn lasting(mut self, seconds: f32) -> Self {
+        self.lifetime = seconds;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Vertex {
+    position : glm::Vec3,
+    color    : glm::Vec4,
+    size     : f32,       // In pixels, for points
+}
+
+struct Shape {
+    vertices   : Vec<Vertex>,   // Pairs of line ends, or points
+    points     : bool,
+    depth_test : bool,
+    remaining  : f32,           // Seconds left to draw it for
+    label      : Option<String>,
+}
+
+// What has been asked for since the last time it was drawn
+static QUEUE: Mutex<Vec<Shape>> = Mutex::new(Vec::new());
+
+fn queue(vertices: Vec<Vertex>, points: bool, style: Style, label: Option<String>) {
+    if let Ok(mut queue) = QUEUE.lock() {
+        queue.push(Shape { vertices, points, depth_test: style.depth_test, remaining: style.lifetime, label });
+    }
+}
+
+fn line_vertices(lines: &[(glm::Vec3, glm::Vec3)], color: glm::Vec4) -> Vec<Vertex> {
+    lines.iter()
+        .flat_map(|(from, to)| [*from, *to])
+        .map(|position| Vertex { position, color, size: 1.0 })
+        .collect()
+}
+
+// Two directions at right angles to the given one and to each other
+fn perpendiculars(direction: &glm::Vec3) -> (glm::Vec3, glm::Vec3) {
+    let other = if direction.y.abs() < 0.9 { glm::vec3(0.0, 1.0, 0.0) } else { glm::vec3(1.0, 0.0, 0.0) };
+    let a = glm::normalize(&glm::cross(direction, &other));
+    let b = glm::cross(direction, &a);
+    (a, b)
+}
+
+pub fn line(from: glm::Vec3, to: glm::Vec3, style: Style) {
+    queue(line_vertices(&[(from, to)], style.color), false, style, None);
+}
+
+// A line with a head of four short lines at the `to` end
+pub fn arrow(from: glm::Vec3, to: glm::Vec3, style: Style) {
+    let length = glm::distance(&from, &to);
+    if length <= 0.0 { return }
+    let direction = (to - from) / length;
+    let (a, b) = perpendiculars(&direction);
+    let head = length.min(1.0) * 0.25;
+    let back = to - direction * head;
+    let mut lines = vec![(from, to)];
+    for side in [a, -a, b, -b] {
+        lines.push((to, back + side * head * 0.5));
+    }
+    queue(line_vertices(&lines, style.color), false, style, None);
+}
+
+// Size in pixels, whatever the distance
+pub fn point(position: glm::Vec3, size: f32, style: Style) {
+    queue(vec![Vertex { position, color: style.color, size }], true, style, None);
+}
+
+// A square grid of lines lying flat, with `divisions` cells along each side
+pub fn grid(center: glm::Vec3, size: f32, divisions: u32, style: Style) {
+    let divisions = divisions.max(1);
+    let half = size * 0.5;
+    let mut lines = vec![];
+    for i in 0..=divisions {
+        let offset = -half + size * i as f32 / divisions as f32;
+        lines.push((center + glm::vec3(offset, 0.0, -half), center + glm::vec3(offset, 0.0, half)));
+        lines.push((center + glm::vec3(-half, 0.0, offset), center + glm::vec3(half, 0.0, offset)));
+    }
+    queue(line_vertices(&lines, style.color), false, style, None);
+}
+
+// Three circles around the center, one in each of the XY, XZ and YZ planes
+pub fn sphere(center: glm::Vec3, radius: f32, style: Style) {
+    const SEGMENTS: usize = 24;
+    let mut lines = vec![];
+    for (a, b) in [(0, 1), (0, 2), (1, 2)] {
+        let on_circle = |i: usize| {
+            let angle = i as f32 / SEGMENTS as f32 * 2.0 * std::f32::consts::PI;
+            let mut p = center;
+            p[a] += radius * angle.cos();
+            p[b] += radius * angle.sin();
+            p
+        };
+        for i in 0..SEGMENTS {
+            lines.push((on_circle(i), on_circle(i + 1)));
+        }
+    }
+    queue(line_vertices(&lines, style.color), false, style, None);
+}
+
+pub fn aabb(aabb: &Aabb, style: Style) {
+    // Corner i has the max of the axes whose bits are set in i, so an edge joins two corners
+    // differing in a single bit
+    let corners = aabb.corners();
+    let mut lines = vec![];
+    for i in 0..8 {
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                lines.push((corners[i], corners[i | bit]));
+            }
+        }
+    }
+    queue(line_vertices(&lines, style.color), false, style, None);
+}
+
+// A marker for a label, see `DebugDraw::labels`
+pub fn text_anchor(position: glm::Vec3, label: &str, style: Style) {
+    queue(vec![Vertex { position, color: style.color, size: 5.0 }], true, style, Some(label.to_string()));
+}
+
+pub struct DebugDraw {
+    pub shader : Shader,
+    shapes     : Vec<Shape>,              // Taken from the queue, and kept while they last
+    labels     : Vec<(glm::Vec3, String)>, // Of the text anchors drawn last
+    vao        : u32,
+    vbo        : u32,
+    capacity   : usize,                   // How many vertices the buffer has room for
+}
+
+impl DebugDraw {
+    pub unsafe fn new() -> Self {
+        let (mut vao, mut vbo) = (0, 0);
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        let stride = mem::size_of::<Vertex>() as i32;
+        let float_size = mem::size_of::<f32>();
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, ptr::null());
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, (3 * float_size) as *const _);
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(2, 1, gl::FLOAT, gl::FALSE, stride, (7 * float_size) as *const _);
+        gl::EnableVertexAttribArray(2);
+        gl::BindVertexArray(0);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+        DebugDraw {
+            shader: ShaderBuilder::new()
+                .attach_file("./shaders/debug_line.vert")
+                .attach_file("./shaders/debug_line.frag")
+                .link(),
+            shapes: vec![],
+            labels: vec![],
+            vao,
+            vbo,
+            capacity: 0,
+        }
+    }
+
+    // Draws everything queued and still alive over the target, which must be bound and hold the
+    // finished scene, and forgets what has had its time. The Camera uniform block must be up to
+    // date.
+    pub unsafe fn draw(&mut self, delta_time: f32, target: &Framebuffer) {
+        if let Ok(mut queue) = QUEUE.lock() {
+            self.shapes.append(&mut queue);
+        }
+        self.labels = self.shapes.iter()
+            .filter_map(|shape| shape.label.clone().map(|label| (shape.vertices[0].position, label)))
+            .collect();
+        if self.shapes.is_empty() { return }
+
+        // Lines and points, with and without depth testing, each drawn from their own range
+        let mut vertices = vec![];
+        let mut ranges = vec![];
+        for depth_test in [true, false] {
+            for points in [false, true] {
+                let start = vertices.len();
+                for shape in self.shapes.iter().filter(|s| s.depth_test == depth_test && s.points == points) {
+                    vertices.extend_from_slice(&shape.vertices);
+                }
+                if vertices.len() > start {
+                    ranges.push((depth_test, points, start as i32, (vertices.len() - start) as i32));
+                }
+            }
+        }
+        self.upload(&vertices);
+
+        target.write_only_color(true);
+        gl::DepthMask(gl::FALSE); // Later passes read the depth of the scene
+        gl::Enable(gl::PROGRAM_POINT_SIZE);
+        self.shader.activate();
+        gl::BindVertexArray(self.vao);
+        for (depth_test, points, start, count) in ranges {
+            if depth_test { gl::Enable(gl::DEPTH_TEST) } else { gl::Disable(gl::DEPTH_TEST) }
+            gl::DrawArrays(if points { gl::POINTS } else { gl::LINES }, start, count);
+        }
+        gl::Enable(gl::DEPTH_TEST);
+        gl::Disable(gl::PROGRAM_POINT_SIZE);
+        gl::DepthMask(gl::TRUE);
+        target.write_only_color(false);
+
+        for shape in self.shapes.iter_mut() {
+            shape.remaining -= delta_time;
+        }
+        self.shapes.retain(|shape| shape.remaining > 0.0);
+    }
+
+    // The labels of the text anchors drawn last, with where they are in the window in pixels from
+    // the top left corner. Anchors behind the camera are left out.
+    pub fn labels(&self, view_projection: &glm::Mat4, width: u32, height: u32) -> Vec<(String, f32, f32)> {
+        self.labels.iter().filter_map(|(p, label)| {
+            let clip = view_projection * glm::vec4(p.x, p.y, p.z, 1.0);
+            if clip.w <= 0.0 { return None }
+            let x = (clip.x / clip.w * 0.5 + 0.5) * width as f32;
+            let y = (0.5 - clip.y / clip.w * 0.5) * height as f32;
+            Some((label.clone(), x, y))
+        }).collect()
+    }
+
+    unsafe fn upload(&mut self, vertices: &[Vertex]) {
+        let size = mem::size_of_val(vertices) as isize;
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        if vertices.len() > self.capacity {
+            // Grow with some headroom, so a few more lines don't reallocate every frame
+            self.capacity = vertices.len().next_power_of_two();
+            let capacity = (self.capacity * mem::size_of::<Vertex>()) as isize;
+            gl::BufferData(gl::ARRAY_BUFFER, capacity, ptr::null(), gl::STREAM_DRAW);
+        }
+        gl::BufferSubData(gl::ARRAY_BUFFER, 0, size, vertices.as_ptr() as *const _);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+}
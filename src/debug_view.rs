@@ -0,0 +1,128 @@
+extern crate nalgebra_glm as glm;
+
+use std::ptr;
+
+use crate::bounds::Frustum;
+use crate::debug_draw::{self, Style};
+use crate::framebuffer::Framebuffer;
+use crate::scene_graph::SceneNode;
+use crate::shader::{Shader, ShaderBuilder};
+
+// Views for finding out what is wrong with a model, each toggled on its own:
+//
+// - wireframe, the triangles of the scene drawn as lines with `glPolygonMode`,
+// - normal lines, a short line along the normal of every vertex, made by a geometry shader,
+// - the box around every node, as it is in the world after being transformed,
+// - axes at the point every node rotates about, red, green and blue for X, Y and Z, and
+// - normal shading, colouring surfaces by their world space normal instead of lighting them.
+//
+// Wireframe and normal shading change how the scene itself is drawn. Everything else is lines
+// drawn over the finished scene, depth tested against it. The boxes and axes are handed to
+// `debug_draw`, so they are drawn along with whatever else it has been given that frame.
+
+pub struct DebugViews {
+    pub wireframe      : bool,
+    pub normal_lines   : bool,
+    pub bounding_boxes : bool,
+    pub axes           : bool,
+    pub normal_shading : bool,
+    pub normal_length  : f32, // Of the normal lines, in world units
+    pub axis_length    : f32,
+
+    pub normal_shader           : Shader, // Used instead of the forward shaders for normal shading
+    pub instanced_normal_shader : Shader,
+    normal_line_shader          : Shader,
+}
+
+impl DebugViews {
+    pub unsafe fn new() -> Self {
+        DebugViews {
+            wireframe: false,
+            normal_lines: false,
+            bounding_boxes: false,
+            axes: false,
+            normal_shading: false,
+            normal_length: 0.3,
+            axis_length: 1.5,
+            normal_shader: ShaderBuilder::new()
+                .attach_file("./shaders/simple.vert")
+                .attach_file("./shaders/debug_normal_shading.frag")
+                .link(),
+            instanced_normal_shader: ShaderBuilder::new()
+                .attach_file("./shaders/instanced.vert")
+                .attach_file("./shaders/debug_normal_shading.frag")
+                .link(),
+            normal_line_shader: ShaderBuilder::new()
+                .attach_file("./shaders/debug_normals.vert")
+                .attach_file("./shaders/debug_normals.geom")
+                .attach_file("./shaders/debug_line.frag")
+                .link(),
+        }
+    }
+
+    // Every shader reading the Camera uniform block
+    pub fn shaders(&self) -> [&Shader; 3] {
+        [&self.normal_shader, &self.instanced_normal_shader, &self.normal_line_shader]
+    }
+
+    // Call around drawing the opaque scene, and again around drawing what is transparent, so both
+    // are drawn as wireframes if that is on. Full-screen passes in between must not be.
+    pub unsafe fn begin_scene(&self) {
+        if self.wireframe {
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+        }
+    }
+
+    pub unsafe fn end_scene(&self) {
+        gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+    }
+
+    // Draws the normal lines of every node in view over the target, which must be bound and hold
+    // the finished scene, and queues up the boxes and axes. The world transformations must be up
+    // to date, and the Camera uniform block too.
+    pub unsafe fn draw(&self, root: &SceneNode, frustum: &Frustum, target: &Framebuffer) {
+        if !(self.normal_lines || self.bounding_boxes || self.axes) { return }
+
+        target.write_only_color(true);
+        if self.normal_lines {
+            self.normal_line_shader.activate();
+            gl::Uniform1f(self.normal_line_shader.get_uniform_location("normal_length"), self.normal_length);
+        }
+        self.visit(root, frustum);
+        target.write_only_color(false);
+    }
+
+    unsafe fn visit(&self, node: &SceneNode, frustum: &Frustum) {
+        let visible = node.world_bounds.is_none_or(|b| frustum.intersects(&b));
+        if node.vao_id != 0 && visible {
+            if self.normal_lines {
+                gl::UniformMatrix4fv(self.normal_line_shader.get_uniform_location("model_matrix"), 1, gl::FALSE, glm::value_ptr(&node.world_transform).as_ptr());
+                gl::BindVertexArray(node.vao_id);
+                gl::DrawElements(gl::TRIANGLES, node.index_count, gl::UNSIGNED_INT, ptr::null());
+            }
+            if self.bounding_boxes {
+                if let Some(bounds) = node.world_bounds {
+                    debug_draw::aabb(&bounds.aabb, Style::new(glm::vec4(1.0, 0.9, 0.2, 1.0)));
+                }
+            }
+        }
+        if self.axes {
+            self.add_axes(node);
+        }
+        for &child in &node.children {
+            self.visit(&*child, frustum);
+        }
+    }
+
+    fn add_axes(&self, node: &SceneNode) {
+        // The node is rotated about where its reference point is moved to before rotating, which
+        // is -reference_point in its own space
+        let r = -node.reference_point;
+        let origin = glm::vec4_to_vec3(&(node.world_transform * glm::vec4(r.x, r.y, r.z, 1.0)));
+        let colors = [glm::vec4(1.0, 0.2, 0.2, 1.0), glm::vec4(0.2, 1.0, 0.2, 1.0), glm::vec4(0.2, 0.4, 1.0, 1.0)];
+        for (axis, color) in colors.iter().enumerate() {
+            let direction = glm::normalize(&node.world_transform.column(axis).xyz());
+            debug_draw::line(origin, origin + direction * self.axis_length, Style::new(*color));
+        }
+    }
+}
@@ -0,0 +1,176 @@
+extern crate nalgebra_glm as glm;
+
+use std::{mem, ptr};
+
+use crate::framebuffer::Framebuffer;
+use crate::lights::{self, PointLight};
+use crate::primitives;
+use crate::shader::{Shader, ShaderBuilder};
+
+// Deferred shading.
+//
+// The scene is first drawn into a G-buffer, storing what each pixel is rather than what it looks
+// like: its albedo, world space normal and world space position. A full-screen pass then lights
+// every pixel once with the moon and the ambient light, however many triangles were drawn over it.
+// The point lights are added on top by drawing a sphere around each, as big as the light reaches,
+// so every pixel is only lit by the lights that can reach it. This is what makes lots of point
+// lights affordable. The lighting itself is shaders/lighting.frag, the same as for forward
+// rendering, so the two can be compared.
+
+pub struct DeferredRenderer {
+    pub gbuffer                   : Framebuffer, // Albedo, normal, position, depth
+    pub geometry_shader           : Shader,
+    pub instanced_geometry_shader : Shader,
+    pub lighting_shader           : Shader,
+    pub point_light_shader        : Shader,
+    empty_vao                     : u32,
+    sphere_vao                    : u32,
+    sphere_index_count            : i32,
+    light_buffer                  : u32,         // Position and radius, and colour, per light
+}
+
+impl DeferredRenderer {
+    pub unsafe fn new(width: u32, height: u32) -> Self {
+        let mut empty_vao = 0;
+        gl::GenVertexArrays(1, &mut empty_vao);
+        let (sphere_vao, sphere_index_count, light_buffer) = light_volume();
+
+        DeferredRenderer {
+            gbuffer: Framebuffer::new(width, height, &[gl::RGBA8, gl::RGB16F, gl::RGB32F], true),
+            geometry_shader: ShaderBuilder::new()
+                .attach_file("./shaders/simple.vert")
+                .attach_file("./shaders/gbuffer.frag")
+                .link(),
+            instanced_geometry_shader: ShaderBuilder::new()
+                .attach_file("./shaders/instanced.vert")
+                .attach_file("./shaders/gbuffer.frag")
+                .link(),
+            lighting_shader: ShaderBuilder::new()
+                .attach_file("./shaders/fullscreen.vert")
+                .attach_file("./shaders/deferred_light.frag")
+                .attach_file("./shaders/lighting.frag")
+                .link(),
+            point_light_shader: ShaderBuilder::new()
+                .attach_file("./shaders/deferred_point_light.vert")
+                .attach_file("./shaders/deferred_point_light.frag")
+                .attach_file("./shaders/lighting.frag")
+                .link(),
+            empty_vao,
+            sphere_vao,
+            sphere_index_count,
+            light_buffer,
+        }
+    }
+
+    pub unsafe fn resize(&mut self, width: u32, height: u32) {
+        self.gbuffer.resize(width, height);
+    }
+
+    // Binds and clears the G-buffer, ready for the scene to be drawn with the geometry shaders
+    pub unsafe fn begin_geometry(&self) {
+        self.gbuffer.bind();
+        let nothing = [0.0f32; 4];
+        for attachment in 0..3 {
+            gl::ClearBufferfv(gl::COLOR, attachment, nothing.as_ptr());
+        }
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+        // Blending would mix normals and positions
+        gl::Disable(gl::BLEND);
+    }
+
+    // Lights the G-buffer into the target, which gets the lit colour, the normals and the ambient
+    // light in its three attachments, like the forward shader would give it. The depth is copied over
+    // too, so the target can be drawn into some more afterwards. Only the first MAX_LIGHTS lights
+    // are used, like in forward rendering.
+    pub unsafe fn light(&self, target: &Framebuffer, sky_color: &glm::Vec4, lights: &[PointLight]) {
+        target.bind();
+        gl::Disable(gl::DEPTH_TEST);
+        for unit in 0..3 {
+            gl::ActiveTexture(gl::TEXTURE0 + unit as u32);
+            gl::BindTexture(gl::TEXTURE_2D, self.gbuffer.colors[unit]);
+        }
+
+        self.lighting_shader.activate();
+        self.bind_gbuffer_samplers(&self.lighting_shader);
+        gl::Uniform4fv(self.lighting_shader.get_uniform_location("sky_color"), 1, sky_color.as_ptr());
+        gl::BindVertexArray(self.empty_vao);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        // The back faces of the spheres, so they still cover the screen with the camera inside,
+        // added onto the colour alone
+        let lights = &lights[..lights.len().min(lights::MAX_LIGHTS)];
+        let instances: Vec<glm::Vec4> = lights.iter()
+            .flat_map(|l| [glm::vec4(l.position.x, l.position.y, l.position.z, l.radius), glm::vec4(l.color.x, l.color.y, l.color.z, 1.0)])
+            .collect();
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.light_buffer);
+        gl::BufferData(gl::ARRAY_BUFFER, mem::size_of_val(instances.as_slice()) as isize, instances.as_ptr() as *const _, gl::STREAM_DRAW);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+        self.point_light_shader.activate();
+        self.bind_gbuffer_samplers(&self.point_light_shader);
+        target.write_only_color(true);
+        gl::BlendFunc(gl::ONE, gl::ONE);
+        gl::CullFace(gl::FRONT);
+        gl::BindVertexArray(self.sphere_vao);
+        gl::DrawElementsInstanced(gl::TRIANGLES, self.sphere_index_count, gl::UNSIGNED_INT, ptr::null(), lights.len() as i32);
+        gl::CullFace(gl::BACK);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        target.write_only_color(false);
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.gbuffer.framebuffer);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.framebuffer);
+        gl::BlitFramebuffer(0, 0, self.gbuffer.width as i32, self.gbuffer.height as i32,
+            0, 0, target.width as i32, target.height as i32, gl::DEPTH_BUFFER_BIT, gl::NEAREST);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, target.framebuffer);
+
+        gl::Enable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+    }
+
+    // Albedo, normal and position on texture units 0, 1 and 2
+    unsafe fn bind_gbuffer_samplers(&self, shader: &Shader) {
+        for (unit, name) in ["albedo_buffer", "normal_buffer", "position_buffer"].iter().enumerate() {
+            gl::Uniform1i(shader.get_uniform_location(name), unit as i32);
+        }
+    }
+}
+
+// A VAO with a sphere of radius 1 around the origin, and a buffer of two vec4s per instance for
+// the lights, returned as the VAO, its index count and the buffer
+unsafe fn light_volume() -> (u32, i32, u32) {
+    // Blown up so that its flat faces, and not just its corners, are at least 1 from the center
+    let mut sphere = primitives::icosphere(1.0, 1, [1.0; 4]);
+    let inner_radius = sphere.indices.chunks(3).map(|triangle| {
+        let p = |i: u32| glm::make_vec3(&sphere.vertices[3 * i as usize..3 * i as usize + 3]);
+        let normal = glm::normalize(&glm::cross(&(p(triangle[1]) - p(triangle[0])), &(p(triangle[2]) - p(triangle[0]))));
+        glm::dot(&normal, &p(triangle[0])).abs()
+    }).fold(1.0f32, f32::min);
+    sphere.vertices.iter_mut().for_each(|x| *x /= inner_radius);
+
+    let (mut vao, mut buffers) = (0, [0u32; 3]);
+    gl::GenVertexArrays(1, &mut vao);
+    gl::GenBuffers(3, buffers.as_mut_ptr());
+    let [vertex_buffer, index_buffer, light_buffer] = buffers;
+    gl::BindVertexArray(vao);
+
+    gl::BindBuffer(gl::ARRAY_BUFFER, vertex_buffer);
+    gl::BufferData(gl::ARRAY_BUFFER, mem::size_of_val(sphere.vertices.as_slice()) as isize, sphere.vertices.as_ptr() as *const _, gl::STATIC_DRAW);
+    gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 0, ptr::null());
+    gl::EnableVertexAttribArray(0);
+
+    gl::BindBuffer(gl::ARRAY_BUFFER, light_buffer);
+    let stride = 2 * mem::size_of::<glm::Vec4>() as i32;
+    for attribute in 0..2 {
+        gl::VertexAttribPointer(1 + attribute, 4, gl::FLOAT, gl::FALSE, stride, (attribute as usize * mem::size_of::<glm::Vec4>()) as *const _);
+        gl::EnableVertexAttribArray(1 + attribute);
+        gl::VertexAttribDivisor(1 + attribute, 1);
+    }
+
+    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, index_buffer);
+    gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, mem::size_of_val(sphere.indices.as_slice()) as isize, sphere.indices.as_ptr() as *const _, gl::STATIC_DRAW);
+
+    gl::BindVertexArray(0);
+    gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    (vao, sphere.index_count, light_buffer)
+}
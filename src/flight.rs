@@ -0,0 +1,174 @@
+extern crate nalgebra_glm as glm;
+
+// A simple rigid-body flight model for the helicopter.
+//
+// The main rotor pushes along the body's up axis with a force proportional to the collective and
+// to the square of the rotor speed. The cyclic tilts the body (and with it the lift) to move
+// horizontally, and the tail rotor turns it around the up axis. Gravity pulls down and drag
+// slows everything down. Everything is measured in scene units, seconds and radians.
+//
+// The body is assumed to face its local -Z axis, with +Y up and +X to the right.
+
+const GRAVITY: f32 = 9.81;
+const MASS: f32 = 1.0;
+
+// Lift at full collective and nominal rotor speed, enough to climb at 1g. Hovering takes half.
+const MAX_LIFT: f32 = 2.0 * MASS * GRAVITY;
+const LINEAR_DRAG: f32 = 0.02;
+
+// Torques at full deflection, around the body's X (pitch), Y (yaw) and Z (roll) axes
+const CYCLIC_TORQUE: f32 = 3.0;
+const TAIL_ROTOR_TORQUE: f32 = 2.5;
+const INERTIA: f32 = 1.0;
+const ANGULAR_DRAG: f32 = 2.0;
+
+pub const NOMINAL_ROTOR_RPM: f32 = 300.0;
+const ROTOR_SPOOL_TIME: f32 = 1.5; // Roughly how long the rotor takes to settle at a new speed
+
+// How quickly the body settles upright and stops sliding once it touches down
+const LANDING_SETTLE_RATE: f32 = 4.0;
+
+// Longest step we integrate in one go, longer frames are split up
+const MAX_STEP: f32 = 1.0 / 120.0;
+
+// Rates at which holding down a key moves the collective and throttle, per second
+pub const COLLECTIVE_RATE: f32 = 0.5;
+pub const THROTTLE_RATE: f32 = 0.5;
+
+pub struct FlightControls {
+    pub collective   : f32, // [0, 1] Blade pitch of the main rotor, i.e. how hard it pushes
+    pub throttle     : f32, // [0, 1] Engine power, the rotor speed follows this
+    pub cyclic_pitch : f32, // [-1, 1] Positive tilts the nose up
+    pub cyclic_roll  : f32, // [-1, 1] Positive rolls to the left
+    pub pedal        : f32, // [-1, 1] Positive yaws to the left
+}
+
+impl FlightControls {
+    // Full throttle with the collective set to hover
+    pub fn new() -> Self {
+        FlightControls {
+            collective   : 0.5,
+            throttle     : 1.0,
+            cyclic_pitch : 0.0,
+            cyclic_roll  : 0.0,
+            pedal        : 0.0,
+        }
+    }
+
+    // Centers the stick and pedals, which are only deflected while their keys are held
+    pub fn center(&mut self) {
+        self.cyclic_pitch = 0.0;
+        self.cyclic_roll = 0.0;
+        self.pedal = 0.0;
+    }
+
+    pub fn adjust_collective(&mut self, delta: f32) {
+        self.collective = (self.collective + delta).clamp(0.0, 1.0);
+    }
+
+    pub fn adjust_throttle(&mut self, delta: f32) {
+        self.throttle = (self.throttle + delta).clamp(0.0, 1.0);
+    }
+}
+
+pub struct FlightModel {
+    pub position         : glm::Vec3,
+    pub velocity         : glm::Vec3,
+    pub orientation      : glm::Quat,
+    pub angular_velocity : glm::Vec3, // In body space
+    pub rotor_rpm        : f32,
+    pub landed           : bool,      // Resting on the ground
+}
+
+impl FlightModel {
+    pub fn new(position: glm::Vec3, orientation: glm::Quat) -> Self {
+        FlightModel {
+            position,
+            velocity: glm::zero(),
+            orientation,
+            angular_velocity: glm::zero(),
+            rotor_rpm: NOMINAL_ROTOR_RPM,
+            landed: false,
+        }
+    }
+
+    // Rotor speed relative to nominal, handy for spinning the rotor meshes
+    pub fn rotor_speed(&self) -> f32 {
+        self.rotor_rpm / NOMINAL_ROTOR_RPM
+    }
+
+    pub fn up(&self) -> glm::Vec3 {
+        glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 1.0, 0.0))
+    }
+
+    pub fn forward(&self) -> glm::Vec3 {
+        glm::quat_rotate_vec3(&self.orientation, &glm::vec3(0.0, 0.0, -1.0))
+    }
+
+    pub fn update(&mut self, controls: &FlightControls, delta_time: f32) {
+        let mut remaining = delta_time;
+        while remaining > 0.0 {
+            let step = remaining.min(MAX_STEP);
+            self.step(controls, step);
+            remaining -= step;
+        }
+    }
+
+    // Keeps the body from sinking below the ground at `ground_height`. Touching down stops the
+    // descent, bleeds off any sliding and spinning, and eases the body level around its heading.
+    pub fn collide_with_ground(&mut self, ground_height: f32, delta_time: f32) {
+        if self.position.y > ground_height {
+            self.landed = false;
+            return
+        }
+
+        self.position.y = ground_height;
+        self.velocity.y = self.velocity.y.max(0.0);
+        self.landed = self.velocity.y == 0.0;
+        if !self.landed { return }
+
+        let settle = (LANDING_SETTLE_RATE * delta_time).min(1.0);
+        self.velocity.x *= 1.0 - settle;
+        self.velocity.z *= 1.0 - settle;
+        self.angular_velocity *= 1.0 - settle;
+
+        let forward = self.forward();
+        let heading = glm::vec3(forward.x, 0.0, forward.z);
+        if glm::length(&heading) > 1e-6 {
+            let level = crate::scene_graph::quat_looking_along(&heading, &glm::vec3(0.0, 1.0, 0.0));
+            self.orientation = glm::quat_slerp(&self.orientation, &level, settle);
+        }
+    }
+
+    fn step(&mut self, controls: &FlightControls, dt: f32) {
+        // The rotor eases towards the speed set by the throttle
+        let target_rpm = controls.throttle * NOMINAL_ROTOR_RPM;
+        self.rotor_rpm += (target_rpm - self.rotor_rpm) * (dt / ROTOR_SPOOL_TIME).min(1.0);
+        let rotor_effect = self.rotor_speed() * self.rotor_speed();
+
+        // Forces, in world space
+        let lift = self.up() * (MAX_LIFT * controls.collective * rotor_effect);
+        let gravity = glm::vec3(0.0, -MASS * GRAVITY, 0.0);
+        let drag = -self.velocity * (LINEAR_DRAG * glm::length(&self.velocity));
+        let acceleration = (lift + gravity + drag) / MASS;
+
+        // Torques, in body space. The rotor needs to be turning for the controls to bite.
+        let torque = glm::vec3(
+            CYCLIC_TORQUE * controls.cyclic_pitch * rotor_effect,
+            TAIL_ROTOR_TORQUE * controls.pedal * rotor_effect,
+            CYCLIC_TORQUE * controls.cyclic_roll * rotor_effect,
+        ) - self.angular_velocity * ANGULAR_DRAG;
+        let angular_acceleration = torque / INERTIA;
+
+        // Semi-implicit Euler integration
+        self.velocity += acceleration * dt;
+        self.position += self.velocity * dt;
+        self.angular_velocity += angular_acceleration * dt;
+
+        let angle = glm::length(&self.angular_velocity) * dt;
+        if angle > 1e-9 {
+            let spin = glm::quat_angle_axis(angle, &glm::normalize(&self.angular_velocity));
+            self.orientation = glm::quat_normalize(&(self.orientation * spin));
+        }
+    }
+}
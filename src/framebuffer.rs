@@ -0,0 +1,144 @@
+use std::ptr;
+
+// Off-screen render target with any number of colour textures and an optional depth texture,
+// all the same size. Everything is a texture, so later passes can sample any of them.
+//
+// A multisampled framebuffer can't be sampled like that, so it is drawn into and then resolved
+// into an ordinary one with `resolve_into`.
+pub struct Framebuffer {
+    pub framebuffer : u32,
+    pub colors      : Vec<u32>, // One texture per colour attachment, in attachment order
+    pub depth       : u32,      // Depth texture, 0 if there is none
+    pub width       : u32,
+    pub height      : u32,
+    pub samples     : u32,      // 0 or 1 for an ordinary framebuffer
+    formats         : Vec<u32>, // Internal format of each colour attachment
+    with_depth      : bool,
+}
+
+impl Framebuffer {
+    // Colour attachments get the internal formats given, e.g. `gl::RGBA16F`
+    pub unsafe fn new(width: u32, height: u32, formats: &[u32], with_depth: bool) -> Self {
+        Framebuffer::new_multisampled(width, height, formats, with_depth, 0)
+    }
+
+    pub unsafe fn new_multisampled(width: u32, height: u32, formats: &[u32], with_depth: bool, samples: u32) -> Self {
+        let mut buffer = Framebuffer {
+            framebuffer: 0,
+            colors: vec![],
+            depth: 0,
+            width: 0,
+            height: 0,
+            samples,
+            formats: formats.to_vec(),
+            with_depth,
+        };
+        buffer.resize(width, height);
+        buffer
+    }
+
+    // Recreates the attachments at the new size, throwing away what was drawn
+    pub unsafe fn resize(&mut self, width: u32, height: u32) {
+        let (width, height) = (width.max(1), height.max(1));
+        if width == self.width && height == self.height { return }
+        self.delete();
+        self.width = width;
+        self.height = height;
+
+        gl::GenFramebuffers(1, &mut self.framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+
+        let mut draw_buffers = vec![];
+        for (i, &format) in self.formats.iter().enumerate() {
+            let attachment = gl::COLOR_ATTACHMENT0 + i as u32;
+            let texture = self.create_texture(format, gl::RGBA);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, self.target(), texture, 0);
+            self.colors.push(texture);
+            draw_buffers.push(attachment);
+        }
+        gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
+
+        if self.with_depth {
+            self.depth = self.create_texture(gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, self.target(), self.depth, 0);
+        }
+
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("Framebuffer with formats {:?} is incomplete", self.formats);
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    // Draws go here from now on, over the whole buffer
+    pub unsafe fn bind(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+    }
+
+    // Stops drawing from touching any colour attachment but the first, for passes whose shaders
+    // only write a colour. `false` lets them all be written again.
+    pub unsafe fn write_only_color(&self, only: bool) {
+        let write = if only { gl::FALSE } else { gl::TRUE };
+        for attachment in 1..self.colors.len() as u32 {
+            gl::ColorMaski(attachment, write, write, write, write);
+        }
+    }
+
+    // Averages the samples of every attachment into the matching attachment of the target,
+    // which must be the same size
+    pub unsafe fn resolve_into(&self, target: &Framebuffer) {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.framebuffer);
+        let (w, h) = (self.width as i32, self.height as i32);
+        for i in 0..self.colors.len().min(target.colors.len()) {
+            let attachment = gl::COLOR_ATTACHMENT0 + i as u32;
+            gl::ReadBuffer(attachment);
+            gl::DrawBuffers(1, &attachment);
+            gl::BlitFramebuffer(0, 0, w, h, 0, 0, w, h, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+        }
+        if self.depth != 0 && target.depth != 0 {
+            gl::BlitFramebuffer(0, 0, w, h, 0, 0, w, h, gl::DEPTH_BUFFER_BIT, gl::NEAREST);
+        }
+
+        // Back to drawing into every attachment
+        let draw_buffers: Vec<u32> = (0..target.colors.len() as u32).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+        gl::DrawBuffers(draw_buffers.len() as i32, draw_buffers.as_ptr());
+        gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    fn target(&self) -> u32 {
+        if self.samples > 1 { gl::TEXTURE_2D_MULTISAMPLE } else { gl::TEXTURE_2D }
+    }
+
+    unsafe fn create_texture(&self, internal_format: u32, format: u32) -> u32 {
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        if self.samples > 1 {
+            gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, texture);
+            gl::TexImage2DMultisample(gl::TEXTURE_2D_MULTISAMPLE, self.samples as i32, internal_format, self.width as i32, self.height as i32, gl::TRUE);
+            gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, 0);
+            return texture;
+        }
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, internal_format as i32, self.width as i32, self.height as i32, 0, format, gl::FLOAT, ptr::null());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+        texture
+    }
+
+    unsafe fn delete(&mut self) {
+        if self.framebuffer != 0 {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteTextures(self.colors.len() as i32, self.colors.as_ptr());
+            if self.depth != 0 {
+                gl::DeleteTextures(1, &self.depth);
+            }
+        }
+        self.colors.clear();
+        self.depth = 0;
+    }
+}
@@ -0,0 +1,366 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashSet;
+
+use crate::animation::{self, Animation, Interpolation, LoopMode, Property, Track};
+use crate::mesh::{self, Mesh};
+use crate::scene_graph::{Node, SceneNode};
+use crate::shader::Shader;
+
+// Importing glTF 2.0 files, both .gltf (with separate or embedded buffers) and binary .glb.
+//
+// Loading a file gives a `GltfAsset` holding plain data: meshes, materials, textures, the node
+// hierarchy and animations. Like `mesh::Model`, it is uploaded once and can then be turned into
+// scene nodes as many times as needed. Of the materials, only the base colour and its texture
+// are drawn, the texture through the first set of texture coordinates.
+//
+// glTF nodes scale before rotating (T * R * S), while our scene nodes rotate before scaling. The
+// two only agree for uniform scales, so a node whose scale isn't uniform, or is animated, gets it
+// from a child node of its own, which holds the mesh and the children of the glTF node.
+
+pub struct Material {
+    pub name               : String,
+    pub base_color         : [f32; 4],
+    pub base_color_texture : Option<usize>, // Index into the textures
+    pub metallic           : f32,
+    pub roughness          : f32,
+    pub emissive           : [f32; 3],
+    pub double_sided       : bool,
+}
+
+// Decoded image, always 8-bit RGBA
+pub struct Texture {
+    pub width  : u32,
+    pub height : u32,
+    pub pixels : Vec<u8>,
+}
+
+impl Texture {
+    // Creates an OpenGL texture with mipmaps, returning its id
+    pub unsafe fn upload(&self) -> u32 {
+        let mut id = 0;
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_2D, id);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA8 as i32, self.width as i32, self.height as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, self.pixels.as_ptr() as *const _);
+        gl::GenerateMipmap(gl::TEXTURE_2D);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+        id
+    }
+}
+
+// Binds the texture, 0 for none, for shaders multiplying their colours by `base_color_texture`
+// when `textured` is set. The shader must be active.
+pub unsafe fn bind_base_color(shader: &Shader, texture_id: u32) {
+    gl::Uniform1i(shader.get_uniform_location("textured"), (texture_id != 0) as i32);
+    gl::ActiveTexture(gl::TEXTURE0);
+    gl::BindTexture(gl::TEXTURE_2D, texture_id);
+}
+
+// A part of a glTF mesh drawn with a single material
+pub struct Primitive {
+    pub mesh     : Mesh,
+    pub material : Option<usize>, // Index into the materials
+}
+
+pub struct GltfMesh {
+    pub name       : String,
+    pub primitives : Vec<Primitive>,
+}
+
+pub struct GltfNode {
+    pub name        : String,
+    pub mesh        : Option<usize>, // Index into the meshes
+    pub translation : glm::Vec3,
+    pub rotation    : glm::Quat,
+    pub scale       : glm::Vec3,
+    pub children    : Vec<usize>,    // Indices into the nodes
+}
+
+// One animated property of one node
+pub struct Channel {
+    pub node          : usize,
+    pub property      : Property,   // Position, Orientation or Scale
+    pub interpolation : Interpolation,
+    pub keyframes     : Vec<(f32, glm::Vec3)>,
+}
+
+pub struct GltfAnimation {
+    pub name     : String,
+    pub channels : Vec<Channel>,
+}
+
+pub struct GltfAsset {
+    pub meshes     : Vec<GltfMesh>,
+    pub materials  : Vec<Material>,
+    pub textures   : Vec<Texture>,
+    pub nodes      : Vec<GltfNode>,
+    pub roots      : Vec<usize>,        // Top level nodes of the default scene
+    pub animations : Vec<GltfAnimation>,
+    vaos           : Vec<Vec<u32>>,     // Per primitive of every mesh, once uploaded
+    texture_ids    : Vec<u32>,          // Of the textures, once uploaded
+}
+
+// The nodes made from an asset, together with its animations retargeted onto them
+pub struct ImportedScene {
+    pub root       : Node,
+    pub nodes      : Vec<*mut SceneNode>, // Same order as the nodes of the asset
+    pub animations : Vec<Animation>,      // Ready to be handed to an `AnimationPlayer`
+}
+
+impl GltfAsset {
+    pub fn load(path: &str) -> Result<Self, String> {
+        println!("Loading glTF from path: {}", path);
+        let before = std::time::Instant::now();
+        let (document, buffers, images) = gltf::import(path)
+            .map_err(|e| format!("Failed to load {}: {}", path, e))?;
+
+        let textures = images.into_iter()
+            .map(to_texture)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to load {}: {}", path, e))?;
+
+        let materials = document.materials().map(|m| {
+            let pbr = m.pbr_metallic_roughness();
+            Material {
+                name: m.name().unwrap_or("").to_string(),
+                base_color: pbr.base_color_factor(),
+                base_color_texture: pbr.base_color_texture().map(|info| info.texture().source().index()),
+                metallic: pbr.metallic_factor(),
+                roughness: pbr.roughness_factor(),
+                emissive: m.emissive_factor(),
+                double_sided: m.double_sided(),
+            }
+        }).collect::<Vec<_>>();
+
+        let mut meshes = vec![];
+        for mesh in document.meshes() {
+            let mut primitives = vec![];
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    println!("Skipping a primitive of {} not made of triangles", mesh.name().unwrap_or("a mesh"));
+                    continue;
+                }
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let vertices: Vec<f32> = match reader.read_positions() {
+                    Some(positions) => positions.flatten().collect(),
+                    None => continue,
+                };
+                let vertex_count = vertices.len() / 3;
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..vertex_count as u32).collect(),
+                };
+                let normals: Vec<f32> = match reader.read_normals() {
+                    Some(normals) => normals.flatten().collect(),
+                    None => mesh::smooth_normals(&vertices, &indices),
+                };
+
+                // Vertex colours are multiplied by the base colour of the material
+                let material = primitive.material().index();
+                let base = material.map_or([1.0; 4], |i| materials[i].base_color);
+                let colors: Vec<f32> = match reader.read_colors(0) {
+                    Some(colors) => colors.into_rgba_f32()
+                        .flat_map(|c| (0..4).map(move |k| c[k] * base[k]))
+                        .collect(),
+                    None => base.iter().cloned().cycle().take(vertex_count * 4).collect(),
+                };
+
+                let mut built = Mesh::new(vertices, normals, colors, indices);
+                if let Some(uvs) = reader.read_tex_coords(0) {
+                    built.uvs = uvs.into_f32().flatten().collect();
+                }
+                primitives.push(Primitive { mesh: built, material });
+            }
+            meshes.push(GltfMesh {
+                name: mesh.name().map_or(format!("mesh_{}", mesh.index()), |n| n.to_string()),
+                primitives,
+            });
+        }
+
+        let nodes = document.nodes().map(|n| {
+            let (t, r, s) = n.transform().decomposed();
+            GltfNode {
+                name: n.name().map_or(format!("node_{}", n.index()), |name| name.to_string()),
+                mesh: n.mesh().map(|m| m.index()),
+                translation: glm::make_vec3(&t),
+                rotation: glm::quat(r[0], r[1], r[2], r[3]),
+                scale: glm::make_vec3(&s),
+                children: n.children().map(|c| c.index()).collect(),
+            }
+        }).collect::<Vec<_>>();
+
+        let roots = match document.default_scene().or_else(|| document.scenes().next()) {
+            Some(scene) => scene.nodes().map(|n| n.index()).collect(),
+            None => vec![],
+        };
+
+        let animations = document.animations().map(|a| {
+            let channels = a.channels().filter_map(|channel| {
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                let times: Vec<f32> = reader.read_inputs()?.collect();
+                let (property, values): (Property, Vec<glm::Vec3>) = match reader.read_outputs()? {
+                    gltf::animation::util::ReadOutputs::Translations(values) =>
+                        (Property::Position, values.map(|v| glm::make_vec3(&v)).collect()),
+                    gltf::animation::util::ReadOutputs::Scales(values) =>
+                        (Property::Scale, values.map(|v| glm::make_vec3(&v)).collect()),
+                    gltf::animation::util::ReadOutputs::Rotations(values) =>
+                        (Property::Orientation, values.into_f32()
+                            .map(|r| animation::rotation_vector(&glm::quat(r[0], r[1], r[2], r[3])))
+                            .collect()),
+                    gltf::animation::util::ReadOutputs::MorphTargetWeights(_) => return None,
+                };
+
+                // Cubic splines store an in-tangent, the value and an out-tangent per keyframe.
+                // We only keep the values and let our own splines make up the tangents.
+                let (interpolation, values) = match channel.sampler().interpolation() {
+                    gltf::animation::Interpolation::Step => (Interpolation::Step, values),
+                    gltf::animation::Interpolation::Linear => (Interpolation::Linear, values),
+                    gltf::animation::Interpolation::CubicSpline =>
+                        (Interpolation::Cubic, values.chunks(3).filter_map(|c| c.get(1).cloned()).collect()),
+                };
+
+                Some(Channel {
+                    node: channel.target().node().index(),
+                    property,
+                    interpolation,
+                    keyframes: times.into_iter().zip(values).collect(),
+                })
+            }).collect();
+            GltfAnimation {
+                name: a.name().map_or(format!("animation_{}", a.index()), |n| n.to_string()),
+                channels,
+            }
+        }).collect::<Vec<_>>();
+
+        let after = std::time::Instant::now();
+        println!("Loaded {} meshes, {} materials, {} textures, {} nodes and {} animations in {:.3}ms.",
+            meshes.len(), materials.len(), textures.len(), nodes.len(), animations.len(),
+            after.duration_since(before).as_micros() as f32 / 1e3);
+
+        Ok(GltfAsset { meshes, materials, textures, nodes, roots, animations, vaos: vec![], texture_ids: vec![] })
+    }
+
+    // Uploads every primitive and texture to the GPU. `create_vao` returns the VAO id of a mesh,
+    // which must have the texture coordinates of textured meshes.
+    pub fn upload(&mut self, create_vao: &dyn Fn(&Mesh) -> u32) {
+        self.vaos = self.meshes.iter()
+            .map(|m| m.primitives.iter().map(|p| create_vao(&p.mesh)).collect())
+            .collect();
+        self.texture_ids = self.textures.iter().map(|t| unsafe { t.upload() }).collect();
+    }
+
+    // The uploaded base colour texture of the primitive, 0 for none
+    fn texture_id(&self, primitive: &Primitive) -> u32 {
+        primitive.material
+            .and_then(|m| self.materials[m].base_color_texture)
+            .map_or(0, |t| self.texture_ids[t])
+    }
+
+    // Builds the node hierarchy of the default scene below a new node named `name`. Meshes
+    // with more than one primitive get a child node per primitive. The asset must outlive
+    // the nodes.
+    pub fn instantiate(&self, name: &str) -> ImportedScene {
+        assert!(self.vaos.len() == self.meshes.len(), "Upload the asset before instantiating it");
+
+        let animated_scale: HashSet<usize> = self.animations.iter()
+            .flat_map(|a| a.channels.iter())
+            .filter(|channel| channel.property == Property::Scale)
+            .map(|channel| channel.node)
+            .collect();
+
+        // The nodes are never dropped, so pointers to them stay valid after the vectors are gone.
+        // `scaled` is where the scale, the mesh and the children of every glTF node end up, which
+        // is the node itself unless it has a scaling node of its own.
+        let mut nodes: Vec<Node> = vec![];
+        let mut pointers: Vec<*mut SceneNode> = vec![];
+        let mut scaled: Vec<*mut SceneNode> = vec![];
+        for (i, n) in self.nodes.iter().enumerate() {
+            let mut node = SceneNode::new();
+            node.name = n.name.clone();
+            node.position = n.translation;
+            node.rotation = n.rotation;
+            let uniform = n.scale.x == n.scale.y && n.scale.y == n.scale.z;
+            let target = if uniform && !animated_scale.contains(&i) {
+                &mut **node as *mut SceneNode
+            } else {
+                let mut scaling = SceneNode::new();
+                scaling.name = format!("{}_scale", n.name);
+                node.add_child(&scaling);
+                &mut **scaling as *mut SceneNode
+            };
+            let target_node = unsafe { &mut *target };
+            target_node.scale = n.scale;
+            if let Some(m) = n.mesh {
+                let primitives = &self.meshes[m].primitives;
+                if primitives.len() == 1 {
+                    target_node.vao_id = self.vaos[m][0];
+                    target_node.index_count = primitives[0].mesh.index_count;
+                    target_node.attach_mesh(&primitives[0].mesh);
+                    target_node.texture_id = self.texture_id(&primitives[0]);
+                } else {
+                    for (i, primitive) in primitives.iter().enumerate() {
+                        let mut child = SceneNode::from_vao(self.vaos[m][i], primitive.mesh.index_count);
+                        child.name = format!("{}_primitive_{}", n.name, i);
+                        child.attach_mesh(&primitive.mesh);
+                        child.texture_id = self.texture_id(primitive);
+                        target_node.add_child(&child);
+                    }
+                }
+            }
+            pointers.push(&mut **node as *mut SceneNode);
+            scaled.push(target);
+            nodes.push(node);
+        }
+        for (i, n) in self.nodes.iter().enumerate() {
+            for &child in &n.children {
+                unsafe { (*scaled[i]).add_child(&*pointers[child]) };
+            }
+        }
+
+        let mut root = SceneNode::new();
+        root.name = name.to_string();
+        for &i in &self.roots {
+            root.add_child(&nodes[i]);
+        }
+
+        let animations = self.animations.iter().map(|a| {
+            let mut built = Animation::new(&a.name, LoopMode::Loop);
+            for channel in &a.channels {
+                let target = match channel.property {
+                    Property::Scale => scaled[channel.node],
+                    _ => pointers[channel.node],
+                };
+                let mut track = Track::new(unsafe { &*target }, channel.property, channel.interpolation);
+                for (time, value) in &channel.keyframes {
+                    track.add_keyframe(*time, *value);
+                }
+                built.tracks.push(track);
+            }
+            built
+        }).collect();
+
+        ImportedScene { root, nodes: pointers, animations }
+    }
+}
+
+fn to_texture(image: gltf::image::Data) -> Result<Texture, String> {
+    use gltf::image::Format;
+    let channels = match image.format {
+        Format::R8 => 1,
+        Format::R8G8 => 2,
+        Format::R8G8B8 => 3,
+        Format::R8G8B8A8 => 4,
+        format => return Err(format!("Unsupported texture format {:?}", format)),
+    };
+    let pixels = image.pixels.chunks(channels).flat_map(|p| match channels {
+        1 => [p[0], p[0], p[0], 255],
+        2 => [p[0], p[0], p[0], p[1]],
+        3 => [p[0], p[1], p[2], 255],
+        _ => [p[0], p[1], p[2], p[3]],
+    }).collect();
+    Ok(Texture { width: image.width, height: image.height, pixels })
+}
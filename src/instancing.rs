@@ -0,0 +1,132 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+use std::{mem, ptr};
+
+use crate::gltf_import;
+use crate::shader::Shader;
+
+// Drawing many copies of a mesh in a single draw call.
+//
+// A `GpuMesh` adds a buffer of per-instance attributes to an existing VAO, which instanced
+// shaders read instead of the model matrix uniform:
+//
+//     layout(location = 3) in mat4 instance_model; // Takes up locations 3 to 6
+//     layout(location = 7) in vec4 instance_tint;
+//
+// so textured meshes take their texture coordinates from UV_LOCATION, after them.
+//
+// The `Batcher` collects what the scene graph wants drawn, and draws everything sharing a VAO
+// and a shader together. A VAO is always drawn with the same texture, if any.
+
+pub const INSTANCE_MODEL_LOCATION: u32 = 3;
+pub const INSTANCE_TINT_LOCATION: u32 = 7;
+pub const UV_LOCATION: u32 = 8;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Instance {
+    pub model : glm::Mat4,
+    pub tint  : glm::Vec4, // Multiplied with the vertex colours
+}
+
+pub struct GpuMesh {
+    pub vao         : u32,
+    pub index_count : i32,
+    pub texture_id  : u32,   // Base colour texture, 0 for none
+    instance_buffer : u32,
+    capacity        : usize, // How many instances the buffer has room for
+}
+
+impl GpuMesh {
+    // Attaches an instance buffer to the VAO, which must not use the instance locations already
+    pub unsafe fn new(vao: u32, index_count: i32, texture_id: u32) -> Self {
+        let mut instance_buffer = 0;
+        gl::GenBuffers(1, &mut instance_buffer);
+
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, instance_buffer);
+        let stride = mem::size_of::<Instance>() as i32;
+        let vec4_size = 4 * mem::size_of::<f32>();
+
+        // A matrix attribute is four vec4 attributes, one per column
+        for column in 0..4 {
+            let location = INSTANCE_MODEL_LOCATION + column as u32;
+            gl::VertexAttribPointer(location, 4, gl::FLOAT, gl::FALSE, stride, (column * vec4_size) as *const _);
+            gl::EnableVertexAttribArray(location);
+            gl::VertexAttribDivisor(location, 1);
+        }
+        gl::VertexAttribPointer(INSTANCE_TINT_LOCATION, 4, gl::FLOAT, gl::FALSE, stride, (4 * vec4_size) as *const _);
+        gl::EnableVertexAttribArray(INSTANCE_TINT_LOCATION);
+        gl::VertexAttribDivisor(INSTANCE_TINT_LOCATION, 1);
+
+        gl::BindVertexArray(0);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+        GpuMesh { vao, index_count, texture_id, instance_buffer, capacity: 0 }
+    }
+
+    // Uploads the instances and draws them all with whichever shader is active
+    pub unsafe fn draw_instanced(&mut self, instances: &[Instance]) {
+        if instances.is_empty() { return }
+        let size = mem::size_of_val(instances) as isize;
+
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_buffer);
+        if instances.len() > self.capacity {
+            // Grow with some headroom, so a slowly growing fleet doesn't reallocate every frame
+            self.capacity = instances.len().next_power_of_two();
+            let capacity = (self.capacity * mem::size_of::<Instance>()) as isize;
+            gl::BufferData(gl::ARRAY_BUFFER, capacity, ptr::null(), gl::STREAM_DRAW);
+        }
+        gl::BufferSubData(gl::ARRAY_BUFFER, 0, size, instances.as_ptr() as *const _);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+        gl::BindVertexArray(self.vao);
+        gl::DrawElementsInstanced(gl::TRIANGLES, self.index_count, gl::UNSIGNED_INT, ptr::null(), instances.len() as i32);
+    }
+}
+
+pub struct Batcher {
+    meshes     : HashMap<u32, GpuMesh>,              // By VAO
+    batches    : HashMap<(u32, u32), Vec<Instance>>, // By shader program and VAO
+    draw_calls : u32,
+}
+
+impl Batcher {
+    pub fn new() -> Self {
+        Batcher { meshes: HashMap::new(), batches: HashMap::new(), draw_calls: 0 }
+    }
+
+    // Queues a copy of the VAO to be drawn by the (instanced) shader
+    pub unsafe fn add(&mut self, vao: u32, index_count: i32, texture_id: u32, shader: &Shader, instance: Instance) {
+        self.meshes.entry(vao).or_insert_with(|| GpuMesh::new(vao, index_count, texture_id));
+        self.batches.entry((shader.program_id, vao)).or_default().push(instance);
+    }
+
+    // Draws and forgets everything queued, a draw call per batch. The shaders get the camera from
+    // its uniform block. Returns the number of draw calls.
+    pub unsafe fn flush(&mut self) -> u32 {
+        let mut keys: Vec<(u32, u32)> = self.batches.iter()
+            .filter(|(_, instances)| !instances.is_empty())
+            .map(|(key, _)| *key)
+            .collect();
+        keys.sort();
+
+        self.draw_calls = 0;
+        let mut active_program = None;
+        for (program, vao) in keys {
+            let shader = Shader { program_id: program };
+            if active_program != Some(program) {
+                shader.activate();
+                active_program = Some(program);
+            }
+            let instances = self.batches.get_mut(&(program, vao)).unwrap();
+            let mesh = self.meshes.get_mut(&vao).unwrap();
+            gltf_import::bind_base_color(&shader, mesh.texture_id);
+            mesh.draw_instanced(instances);
+            instances.clear(); // Keeping the allocation for the next frame
+            self.draw_calls += 1;
+        }
+        self.draw_calls
+    }
+}
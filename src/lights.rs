@@ -0,0 +1,61 @@
+extern crate nalgebra_glm as glm;
+
+use rand::Rng;
+
+use crate::collision::TriangleGrid;
+use crate::noise;
+use crate::uniform_buffer::{BlockLayout, Std140Type, UniformBuffer};
+
+// Point lights, handed to the shaders through a uniform block, which shaders/lighting.frag
+// declares for all of them:
+//
+//     layout(std140, binding = 1) uniform Lights {
+//         vec4 light_positions[MAX_LIGHTS]; // Radius in w
+//         vec4 light_colors[MAX_LIGHTS];
+//         int light_count;
+//     };
+//
+// Deferred shading draws a volume per light instead, with the lights as instance attributes.
+
+pub const LIGHTS_BINDING: u32 = 1;
+pub const MAX_LIGHTS: usize = 128;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position : glm::Vec3,
+    pub color    : glm::Vec3,
+    pub radius   : f32, // Lights nothing further away than this
+}
+
+pub fn light_block() -> BlockLayout {
+    BlockLayout::new("Lights")
+        .array("light_positions", Std140Type::Vec4, MAX_LIGHTS)
+        .array("light_colors", Std140Type::Vec4, MAX_LIGHTS)
+        .field("light_count", Std140Type::Int)
+}
+
+// Fills in the block with the lights, leaving out any past MAX_LIGHTS
+pub fn set_lights(buffer: &mut UniformBuffer, lights: &[PointLight]) {
+    let count = lights.len().min(MAX_LIGHTS);
+    for (i, light) in lights[..count].iter().enumerate() {
+        buffer.set_element("light_positions", i, &glm::vec4(light.position.x, light.position.y, light.position.z, light.radius));
+        buffer.set_element("light_colors", i, &glm::vec4(light.color.x, light.color.y, light.color.z, 1.0));
+    }
+    buffer.set("light_count", &(count as i32));
+}
+
+// Coloured lights hovering just above the ground, scattered over a square of the terrain
+pub fn scatter(terrain: &TriangleGrid, count: usize, center: glm::Vec2, extent: f32, seed: u64) -> Vec<PointLight> {
+    let mut rng = noise::seeded_rng(seed);
+    (0..count).map(|_| {
+        let x = center.x + rng.gen_range(-extent..extent);
+        let z = center.y + rng.gen_range(-extent..extent);
+        let ground = terrain.height_at(x, z).unwrap_or(0.0);
+        let hue = rng.gen_range(0.0..2.0 * std::f32::consts::PI);
+        PointLight {
+            position: glm::vec3(x, ground + rng.gen_range(1.0..4.0), z),
+            color: glm::vec3(0.6 + 0.4 * hue.cos(), 0.6 + 0.4 * (hue + 2.1).cos(), 0.6 + 0.4 * (hue + 4.2).cos()) * 2.0,
+            radius: rng.gen_range(8.0..20.0),
+        }
+    }).collect()
+}
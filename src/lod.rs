@@ -0,0 +1,391 @@
+extern crate nalgebra_glm as glm;
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::bounds::BoundingSphere;
+use crate::mesh::Mesh;
+use crate::mesh_cache;
+
+// Level of detail.
+//
+// Coarser versions of a mesh are made by repeatedly collapsing the edge whose removal changes the
+// surface the least, as measured by quadric error metrics (Garland & Heckbert). Vertices on open
+// borders are never moved, so tiles of the same mesh still meet up when simplified separately.
+// When drawing, the level is picked from how large the node appears on screen.
+
+// Fractions of the original triangles kept by each level, finest first
+pub const DEFAULT_LOD_RATIOS: [f32; 4] = [1.0, 0.4, 0.15, 0.05];
+
+// Smallest fraction of the screen height a node must cover for each level to be used
+pub const DEFAULT_LOD_SCREEN_SIZES: [f32; 4] = [0.8, 0.35, 0.12, 0.0];
+
+// Collapses that turn a triangle further than this (as the cosine of the angle) are rejected
+const MIN_NORMAL_COSINE: f64 = 0.2;
+
+#[derive(Clone, Copy)]
+pub struct LodLevel {
+    pub vao_id          : u32,
+    pub index_count     : i32,
+    pub min_screen_size : f32, // Used when the node covers at least this much of the screen height
+}
+
+// What we need to know about the camera to pick levels
+pub struct LodView {
+    pub eye  : glm::Vec3, // In world space
+    pub fovy : f32,       // measured in radians
+}
+
+impl LodView {
+    // Roughly how large a fraction of the screen height the sphere covers
+    pub fn screen_size(&self, sphere: &BoundingSphere) -> f32 {
+        let distance = glm::distance(&self.eye, &sphere.center);
+        if distance <= sphere.radius { return f32::INFINITY }
+        sphere.radius / (distance * (self.fovy * 0.5).tan())
+    }
+
+    // Levels are expected finest first. Falls back to the coarsest level.
+    pub fn select<'a>(&self, levels: &'a [LodLevel], sphere: &BoundingSphere) -> Option<&'a LodLevel> {
+        let size = self.screen_size(sphere);
+        levels.iter().find(|level| size >= level.min_screen_size).or(levels.last())
+    }
+}
+
+// One simplified mesh per ratio, in the same order
+pub fn generate_lods(mesh: &Mesh, ratios: &[f32]) -> Vec<Mesh> {
+    ratios.iter().map(|&ratio| simplify(mesh, ratio)).collect()
+}
+
+pub fn lod_cache_path(path: &str) -> String {
+    format!("{}.lods.cache", path)
+}
+
+// The simplified levels of every tile, one list per tile. With the file the tiles were cut from
+// given, they are read from its level of detail cache if that was made from the same file with
+// the same tiles and ratios, and written there otherwise, since simplifying takes a while.
+pub fn generate_tile_lods(tiles: &[Mesh], ratios: &[f32], source: Option<&str>) -> Vec<Vec<Mesh>> {
+    let names: Vec<String> = (0..tiles.len())
+        .flat_map(|i| ratios.iter().map(move |ratio| format!("tile {} of {}, ratio {}", i, tiles.len(), ratio)))
+        .collect();
+
+    let cached = source.and_then(|path| mesh_cache::read_cache(&lod_cache_path(path), path));
+    let meshes: Vec<Mesh> = match cached {
+        Some(cached) if cached.iter().map(|(name, _)| name).eq(names.iter()) => {
+            cached.into_iter().map(|(_, mesh)| mesh).collect()
+        }
+        _ => {
+            let meshes: Vec<Mesh> = tiles.iter().flat_map(|tile| generate_lods(tile, ratios)).collect();
+            if let Some(path) = source {
+                let named: Vec<(String, Mesh)> = names.into_iter().zip(meshes.iter().cloned()).collect();
+                if let Err(e) = mesh_cache::write_cache(&lod_cache_path(path), path, &named) {
+                    println!("{}", e);
+                }
+            }
+            meshes
+        }
+    };
+
+    let mut meshes = meshes.into_iter();
+    tiles.iter().map(|_| meshes.by_ref().take(ratios.len()).collect()).collect()
+}
+
+// Symmetric 4x4 error quadric, storing only the upper triangle
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn from_plane(a: f64, b: f64, c: f64, d: f64, weight: f64) -> Self {
+        Quadric([
+            a * a, a * b, a * c, a * d,
+                   b * b, b * c, b * d,
+                          c * c, c * d,
+                                 d * d,
+        ].map(|q| q * weight))
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for i in 0..10 {
+            self.0[i] += other.0[i];
+        }
+    }
+
+    fn error(&self, v: &glm::DVec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (v.x, v.y, v.z);
+        q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9]
+    }
+
+    // The position minimizing the error, if the quadric is well conditioned
+    fn optimum(&self) -> Option<glm::DVec3> {
+        let q = &self.0;
+        let a = glm::DMat3::new(
+            q[0], q[1], q[2],
+            q[1], q[4], q[5],
+            q[2], q[5], q[7],
+        );
+        if glm::determinant(&a).abs() < 1e-12 { return None }
+        Some(glm::inverse(&a) * -glm::DVec3::new(q[3], q[6], q[8]))
+    }
+}
+
+// A candidate collapse, ordered so that the heap pops the cheapest first
+struct Collapse {
+    cost     : f64,
+    vertices : (u32, u32),
+    versions : (u32, u32), // To tell whether either vertex has changed since this was queued
+    target   : glm::DVec3,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool { self.cost == other.cost }
+}
+impl Eq for Collapse {}
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+    }
+}
+
+struct Simplifier {
+    positions    : Vec<glm::DVec3>,
+    quadrics     : Vec<Quadric>,
+    versions     : Vec<u32>,
+    locked       : Vec<bool>,        // Border vertices, which must stay put
+    faces        : Vec<[u32; 3]>,
+    face_alive   : Vec<bool>,
+    vertex_faces : Vec<Vec<usize>>,  // Faces around each vertex, possibly including dead ones
+    heap         : BinaryHeap<Collapse>,
+}
+
+impl Simplifier {
+    fn new(mesh: &Mesh) -> Self {
+        let positions: Vec<glm::DVec3> = mesh.vertices.chunks(3)
+            .map(|p| glm::DVec3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+        let faces: Vec<[u32; 3]> = mesh.indices.chunks(3).map(|f| [f[0], f[1], f[2]]).collect();
+
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        let mut vertex_faces = vec![vec![]; positions.len()];
+        let mut edge_uses = HashMap::<(u32, u32), u32>::new();
+        for (i, face) in faces.iter().enumerate() {
+            let [p0, p1, p2] = face.map(|v| positions[v as usize]);
+            let cross = glm::cross(&(p1 - p0), &(p2 - p0));
+            let area = glm::length(&cross) * 0.5;
+            if area > 0.0 {
+                let n = cross.normalize();
+                let plane = Quadric::from_plane(n.x, n.y, n.z, -glm::dot(&n, &p0), area);
+                for &v in face {
+                    quadrics[v as usize].add(&plane);
+                }
+            }
+            for j in 0..3 {
+                let (a, b) = (face[j], face[(j + 1) % 3]);
+                vertex_faces[a as usize].push(i);
+                *edge_uses.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+
+        // Edges used by a single face lie on the border
+        let mut locked = vec![false; positions.len()];
+        for (&(a, b), &uses) in &edge_uses {
+            if uses == 1 {
+                locked[a as usize] = true;
+                locked[b as usize] = true;
+            }
+        }
+
+        let mut simplifier = Simplifier {
+            versions: vec![0; positions.len()],
+            positions,
+            quadrics,
+            locked,
+            face_alive: vec![true; faces.len()],
+            faces,
+            vertex_faces,
+            heap: BinaryHeap::new(),
+        };
+        for &(a, b) in edge_uses.keys() {
+            simplifier.queue(a, b);
+        }
+        simplifier
+    }
+
+    fn queue(&mut self, a: u32, b: u32) {
+        if self.locked[a as usize] || self.locked[b as usize] { return }
+
+        let mut quadric = self.quadrics[a as usize];
+        quadric.add(&self.quadrics[b as usize]);
+
+        let (pa, pb) = (self.positions[a as usize], self.positions[b as usize]);
+        let mut candidates = vec![pa, pb, (pa + pb) * 0.5];
+        if let Some(optimum) = quadric.optimum() {
+            // Don't let a badly conditioned optimum fly off
+            if glm::distance(&optimum, &pa) < 2.0 * glm::distance(&pa, &pb) {
+                candidates.push(optimum);
+            }
+        }
+        let (cost, target) = candidates.iter()
+            .map(|p| (quadric.error(p), *p))
+            .min_by(|x, y| x.0.partial_cmp(&y.0).unwrap_or(Ordering::Equal))
+            .unwrap();
+
+        self.heap.push(Collapse {
+            cost,
+            vertices: (a, b),
+            versions: (self.versions[a as usize], self.versions[b as usize]),
+            target,
+        });
+    }
+
+    fn live_faces(&self, v: u32) -> Vec<usize> {
+        let mut faces: Vec<usize> = self.vertex_faces[v as usize].iter()
+            .cloned()
+            .filter(|&f| self.face_alive[f] && self.faces[f].contains(&v))
+            .collect();
+        faces.sort_unstable();
+        faces.dedup();
+        faces
+    }
+
+    // Would moving `a` and `b` to `target` fold any of the surrounding triangles over?
+    fn flips(&self, a: u32, b: u32, target: &glm::DVec3) -> bool {
+        for v in [a, b] {
+            for f in self.live_faces(v) {
+                let face = self.faces[f];
+                if face.contains(&a) && face.contains(&b) { continue } // These disappear
+
+                let before = face.map(|i| self.positions[i as usize]);
+                let after = face.map(|i| if i == v { *target } else { self.positions[i as usize] });
+                let n0 = glm::cross(&(before[1] - before[0]), &(before[2] - before[0]));
+                let n1 = glm::cross(&(after[1] - after[0]), &(after[2] - after[0]));
+                let (l0, l1) = (glm::length(&n0), glm::length(&n1));
+                if l1 < 1e-12 { return true }
+                if l0 > 1e-12 && glm::dot(&n0, &n1) / (l0 * l1) < MIN_NORMAL_COSINE { return true }
+            }
+        }
+        false
+    }
+
+    fn run(&mut self, target_faces: usize) {
+        let mut face_count = self.faces.len();
+        while face_count > target_faces {
+            let collapse = match self.heap.pop() {
+                Some(c) => c,
+                None => break,
+            };
+            let (a, b) = collapse.vertices;
+            if collapse.versions != (self.versions[a as usize], self.versions[b as usize]) { continue }
+            if self.flips(a, b, &collapse.target) { continue }
+
+            // Merge b into a
+            for f in self.live_faces(b) {
+                if self.faces[f].contains(&a) {
+                    self.face_alive[f] = false;
+                    face_count -= 1;
+                } else {
+                    for i in self.faces[f].iter_mut() {
+                        if *i == b { *i = a }
+                    }
+                    self.vertex_faces[a as usize].push(f);
+                }
+            }
+            let qb = self.quadrics[b as usize];
+            self.quadrics[a as usize].add(&qb);
+            self.positions[a as usize] = collapse.target;
+            self.versions[a as usize] += 1;
+            self.versions[b as usize] += 1;
+            self.vertex_faces[b as usize].clear();
+
+            let mut neighbours: Vec<u32> = self.live_faces(a).iter()
+                .flat_map(|&f| self.faces[f].to_vec())
+                .filter(|&v| v != a)
+                .collect();
+            neighbours.sort_unstable();
+            neighbours.dedup();
+            for n in neighbours {
+                self.queue(a, n);
+            }
+        }
+    }
+}
+
+// A copy of the mesh with about `ratio` of its triangles left. Vertices keep their own colour
+// and normal, only their positions move.
+pub fn simplify(mesh: &Mesh, ratio: f32) -> Mesh {
+    let triangle_count = mesh.indices.len() / 3;
+    let target = ((triangle_count as f32 * ratio).ceil() as usize).max(1);
+    if target >= triangle_count {
+        return Mesh::new(mesh.vertices.clone(), mesh.normals.clone(), mesh.colors.clone(), mesh.indices.clone());
+    }
+
+    let mut simplifier = Simplifier::new(mesh);
+    simplifier.run(target);
+
+    // Compact the vertices still in use
+    let mut remap = vec![u32::MAX; simplifier.positions.len()];
+    let (mut vertices, mut normals, mut colors, mut indices) = (vec![], vec![], vec![], vec![]);
+    for (f, face) in simplifier.faces.iter().enumerate() {
+        if !simplifier.face_alive[f] { continue }
+        for &v in face {
+            let v = v as usize;
+            if remap[v] == u32::MAX {
+                remap[v] = (vertices.len() / 3) as u32;
+                let p = simplifier.positions[v];
+                vertices.extend([p.x as f32, p.y as f32, p.z as f32]);
+                if mesh.normals.len() >= 3 * v + 3 {
+                    normals.extend_from_slice(&mesh.normals[3 * v..3 * v + 3]);
+                }
+                colors.extend_from_slice(&mesh.colors[4 * v..4 * v + 4]);
+            }
+            indices.push(remap[v]);
+        }
+    }
+    Mesh::new(vertices, normals, colors, indices)
+}
+
+// Cuts the mesh into a grid of tiles over the XZ plane. Each triangle goes to the tile its
+// center falls in, and the vertices along the cuts are duplicated into both tiles.
+pub fn split_into_tiles(mesh: &Mesh, tiles_x: usize, tiles_z: usize) -> Vec<Mesh> {
+    let aabb = &mesh.bounds.aabb;
+    let size = glm::vec2((aabb.max.x - aabb.min.x) / tiles_x as f32, (aabb.max.z - aabb.min.z) / tiles_z as f32);
+
+    let mut tiles: Vec<(Vec<u32>, HashMap<u32, u32>)> = (0..tiles_x * tiles_z).map(|_| (vec![], HashMap::new())).collect();
+    for face in mesh.indices.chunks(3) {
+        let center = face.iter()
+            .map(|&i| glm::vec2(mesh.vertices[3 * i as usize], mesh.vertices[3 * i as usize + 2]))
+            .fold(glm::Vec2::zeros(), |sum, p| sum + p) / 3.0;
+        let tx = (((center.x - aabb.min.x) / size.x.max(1e-6)) as usize).min(tiles_x - 1);
+        let tz = (((center.y - aabb.min.z) / size.y.max(1e-6)) as usize).min(tiles_z - 1);
+
+        let (indices, remap) = &mut tiles[tz * tiles_x + tx];
+        for &i in face {
+            let next = remap.len() as u32;
+            indices.push(*remap.entry(i).or_insert(next));
+        }
+    }
+
+    tiles.into_iter()
+        .filter(|(indices, _)| !indices.is_empty())
+        .map(|(indices, remap)| {
+            let mut order: Vec<(u32, u32)> = remap.into_iter().map(|(old, new)| (new, old)).collect();
+            order.sort_unstable();
+
+            let (mut vertices, mut normals, mut colors) = (vec![], vec![], vec![]);
+            for (_, old) in order {
+                let v = old as usize;
+                vertices.extend_from_slice(&mesh.vertices[3 * v..3 * v + 3]);
+                if mesh.normals.len() >= 3 * v + 3 {
+                    normals.extend_from_slice(&mesh.normals[3 * v..3 * v + 3]);
+                }
+                colors.extend_from_slice(&mesh.colors[4 * v..4 * v + 4]);
+            }
+            Mesh::new(vertices, normals, colors, indices)
+        })
+        .collect()
+}
@@ -19,19 +19,61 @@ mod util;
 mod mesh;
 mod scene_graph;
 mod toolbox;
+mod animation;
+mod path;
+mod flight;
+mod collision;
+mod bounds;
+mod lod;
+mod picking;
+mod scene;
+mod gltf_import;
+mod mesh_cache;
+mod mesh_io;
+mod primitives;
+mod noise;
+mod instancing;
+mod uniform_buffer;
+mod framebuffer;
+mod post;
+mod lights;
+mod deferred;
+mod ssao;
+mod skybox;
+mod render_config;
+mod transparency;
+mod debug_view;
+mod debug_draw;
 
 use scene_graph::SceneNode;
+use bounds::{Frustum, CullingStats};
+use lod::{LodLevel, LodView};
+use picking::{IdBuffer, Ray};
+use instancing::{Batcher, Instance, UV_LOCATION};
+use scene::SceneDescription;
+use gltf_import::GltfAsset;
+use uniform_buffer::UniformBuffer;
+use post::PostChain;
+use deferred::DeferredRenderer;
+use transparency::{TransparencyPass, TransparencyMode, TransparentDraw};
+use debug_view::DebugViews;
+use debug_draw::DebugDraw;
 
 use gl::types::GLuint;
-use glutin::event::{Event, WindowEvent, DeviceEvent, KeyboardInput, ElementState::{Pressed, Released}, VirtualKeyCode::{self, *}};
+use glutin::event::{Event, WindowEvent, DeviceEvent, KeyboardInput, MouseButton, ElementState::{Pressed, Released}, VirtualKeyCode::{self, *}};
 use glutin::event_loop::ControlFlow;
-use mesh::Helicopter;
+use path::{Path, PathFollower};
+use flight::{FlightModel, FlightControls};
+use animation::AnimationPlayer;
 
 
 // initial window size
 const INITIAL_SCREEN_W: u32 = 800;
 const INITIAL_SCREEN_H: u32 = 600;
 
+// The terrain is cut into this many tiles along each axis, so far away tiles can be drawn coarser
+const TERRAIN_TILES: usize = 4;
+
 // == // Helper functions to make interacting with OpenGL a little bit prettier. You *WILL* need these! // == //
 
 // Get the size of an arbitrary array of numbers measured in bytes
@@ -150,7 +192,80 @@ unsafe fn create_vao(vertices: &Vec<f32>, indices: &Vec<u32>, colours: &Vec<f32>
     vao
 }
 
+// Gives a VAO from `create_vao` the texture coordinates of its vertices, in a buffer of their
+// own at UV_LOCATION
+unsafe fn add_uvs(vao_id: u32, uvs: &[f32]) {
+    if uvs.is_empty() { return }
+    let mut vbo: GLuint = 0;
+    gl::BindVertexArray(vao_id);
+    gl::GenBuffers(1, &mut vbo);
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+    gl::BufferData(gl::ARRAY_BUFFER, byte_size_of_array(uvs), pointer_to_array(uvs), gl::STATIC_DRAW);
+    gl::VertexAttribPointer(UV_LOCATION, 2, gl::FLOAT, gl::FALSE, 0, ptr::null());
+    gl::EnableVertexAttribArray(UV_LOCATION);
+    gl::BindVertexArray(0);
+    gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+}
+
+// The default levels of detail, the finest being the full mesh already uploaded as `vao_id`, and
+// the rest the coarser meshes given, which are uploaded
+unsafe fn create_lod_levels(vao_id: u32, index_count: i32, coarser: &[mesh::Mesh]) -> Vec<LodLevel> {
+    let finest = LodLevel { vao_id, index_count, min_screen_size: lod::DEFAULT_LOD_SCREEN_SIZES[0] };
+    std::iter::once(finest)
+        .chain(coarser.iter().zip(lod::DEFAULT_LOD_SCREEN_SIZES[1..].iter()).map(|(level, &min_screen_size)| LodLevel {
+            vao_id: create_vao(&level.vertices, &level.indices, &level.colors, &level.normals),
+            index_count: level.index_count,
+            min_screen_size,
+        }))
+        .collect()
+}
+
+// Gives every node drawing `mesh`, of me and everything below me, the same levels of detail
+fn share_lod_levels(node: &mut SceneNode, mesh: &mesh::Mesh, lods: &[LodLevel]) {
+    if ptr::eq(node.mesh, mesh) {
+        node.lods = lods.to_vec();
+    }
+    for &child in &node.children {
+        share_lod_levels(unsafe { &mut *child }, mesh, lods);
+    }
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // `build-cache [directory]` writes mesh caches for every OBJ file in resources/ and quits
+    if args.get(1).map(|a| a.as_str()) == Some("build-cache") {
+        let directory = args.get(2).map_or("./resources", |d| d.as_str());
+        match mesh_cache::build_all(directory) {
+            Ok(written) => println!("Wrote {} mesh caches", written),
+            Err(e) => { eprintln!("{}", e); std::process::exit(1) }
+        }
+        return;
+    }
+
+    // The helicopters come from ./resources/main_scene.ron or `--scene <path>`, and more of the
+    // scene may be loaded from a glTF file, given as `--gltf <path>`
+    let scene_path = args.iter().position(|a| a == "--scene").and_then(|i| args.get(i + 1)).cloned()
+        .unwrap_or("./resources/main_scene.ron".to_string());
+    let gltf_path = args.iter().position(|a| a == "--gltf").and_then(|i| args.get(i + 1)).cloned();
+
+    // The terrain is an OBJ file or a heightmap image given as `--terrain <path>`, or generated
+    // from `--seed <number>`
+    let terrain_path = args.iter().position(|a| a == "--terrain").and_then(|i| args.get(i + 1)).cloned()
+        .unwrap_or("./resources/lunarsurface.obj".to_string());
+    let terrain_seed = args.iter().position(|a| a == "--seed").and_then(|i| args.get(i + 1))
+        .map(|s| s.parse::<u64>().expect("The seed must be a number"));
+
+    // The six images of the skybox are looked for in `--skybox <directory>`
+    let skybox_path = args.iter().position(|a| a == "--skybox").and_then(|i| args.get(i + 1)).cloned()
+        .unwrap_or("./resources/skybox".to_string());
+
+    // How many nodes are drawn and culled is reported every second with `--stats`, or after F8
+    let report_stats = args.iter().any(|a| a == "--stats");
+
+    // MSAA and anti-aliasing, from ./resources/render.ron or `--config <path>`, and the command line
+    let render_config = render_config::RenderConfig::from_args(&args).unwrap_or_else(|e| panic!("{}", e));
+
     // Set up the necessary objects to deal with windows and event handling
     let el = glutin::event_loop::EventLoop::new();
     let wb = glutin::window::WindowBuilder::new()
@@ -158,6 +273,7 @@ fn main() {
         .with_resizable(true)
         .with_inner_size(glutin::dpi::LogicalSize::new(INITIAL_SCREEN_W, INITIAL_SCREEN_H));
     let cb = glutin::ContextBuilder::new()
+        .with_multisampling(render_config.window_samples)
         .with_vsync(true);
     let windowed_context = cb.build_windowed(wb, &el).unwrap();
     // Uncomment these if you want to use the mouse for controls, but want it to be confined to the screen and/or invisible.
@@ -174,6 +290,11 @@ fn main() {
     // Make a reference of this tuple to send to the render thread
     let mouse_delta = Arc::clone(&arc_mouse_delta);
 
+    // Set up shared slot for the cursor position of the latest unhandled left click
+    let arc_mouse_click = Arc::new(Mutex::new(None::<(f32, f32)>));
+    // Make a reference of this slot to send to the render thread
+    let mouse_click = Arc::clone(&arc_mouse_click);
+
     // Set up shared tuple for tracking changes to the window size
     let arc_window_size = Arc::new(Mutex::new((INITIAL_SCREEN_W, INITIAL_SCREEN_H, false)));
     // Make a reference of this tuple to send to the render thread
@@ -191,6 +312,8 @@ fn main() {
         };
 
         let mut window_aspect_ratio = INITIAL_SCREEN_W as f32 / INITIAL_SCREEN_H as f32;
+        let mut window_width = INITIAL_SCREEN_W;
+        let mut window_height = INITIAL_SCREEN_H;
        
         // Camera variables a)
         let mut camera_position = glm::vec3(0.0, 0.0, 0.0);
@@ -198,12 +321,18 @@ fn main() {
         let mut yaw: f32 = 0.0;
         let camera_speed: f32 = 30.0;
 
+        // How far above the ground the camera and the autopilot keep themselves
+        let camera_clearance: f32 = 2.0;
+        let autopilot_clearance: f32 = 5.0;
+
         // Set up openGL
         unsafe {
             gl::Enable(gl::DEPTH_TEST);
             gl::DepthFunc(gl::LESS);
             gl::Enable(gl::CULL_FACE);
-            gl::Disable(gl::MULTISAMPLE);
+            // Does nothing for buffers without samples, but has to be on for the multisampled
+            // scene buffer as well as the window
+            gl::Enable(gl::MULTISAMPLE);
             gl::Enable(gl::BLEND);
             gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
             gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
@@ -216,38 +345,110 @@ fn main() {
         }
 
         // == // Set up your VAO around here
-        let terrain_path: &str = "./resources/lunarsurface.obj";
-        let lunarsurface: mesh::Mesh = mesh::Terrain::load(&terrain_path);
-
-        let vehicle_path: &str = "./resources/helicopter.obj";
-        let helicopter: Helicopter = mesh::Helicopter::load(&vehicle_path);
+        let lunarsurface = match (terrain_seed, terrain_path.as_str()) {
+            (Some(seed), _) => mesh::Terrain::generate(&mesh::TerrainSettings::new(seed)),
+            (None, path) if path.to_lowercase().ends_with(".obj") => mesh::Terrain::load(path),
+            (None, path) => mesh::Terrain::from_heightmap(path, 400.0, 30.0).expect("Failed to load terrain"),
+        };
+        let terrain_collider = mesh::Terrain::collider(&lunarsurface);
 
-        let mut terrain_vao = unsafe {create_vao(&lunarsurface.vertices, &lunarsurface.indices, &lunarsurface.colors, &lunarsurface.normals)};
-        let mut body_vao = unsafe {create_vao(&helicopter.body.vertices, &helicopter.body.indices, &helicopter.body.colors, &helicopter.body.normals)};
-        let mut door_vao = unsafe {create_vao(&helicopter.door.vertices, &helicopter.door.indices, &helicopter.door.colors, &helicopter.door.normals)};
-        let mut main_rotor_vao = unsafe {create_vao(&helicopter.main_rotor.vertices, &helicopter.main_rotor.indices, &helicopter.main_rotor.colors, &helicopter.main_rotor.normals)};
-        let mut tail_rotor_vao = unsafe {create_vao(&helicopter.tail_rotor.vertices, &helicopter.tail_rotor.indices, &helicopter.tail_rotor.colors, &helicopter.tail_rotor.normals)};
+        let description = SceneDescription::load(&scene_path).expect("Failed to load scene file");
+        let mut loaded_scene = description
+            .instantiate(&|mesh| unsafe { create_vao(&mesh.vertices, &mesh.indices, &mesh.colors, &mesh.normals) })
+            .expect("Failed to build scene");
+        println!("Loaded scene from {}", scene_path);
 
         let mut parent_node = SceneNode::new();
-        let mut terrain_node = SceneNode::from_vao(terrain_vao, lunarsurface.index_count);
-        let mut helicopter_body_node = SceneNode::from_vao(body_vao, helicopter.body.index_count);
-        let mut helicopter_door_node = SceneNode::from_vao(door_vao, helicopter.door.index_count);
-        let mut helicopter_main_rotor_node = SceneNode::from_vao(main_rotor_vao, helicopter.main_rotor.index_count);
-        let mut helicopter_tail_rotor_node = SceneNode::from_vao(tail_rotor_vao, helicopter.tail_rotor.index_count);
-        
+        let mut terrain_node = SceneNode::new();
+
         terrain_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-        helicopter_body_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-        helicopter_door_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-        helicopter_main_rotor_node.reference_point = glm::vec3(0.0, 0.0, 0.0);
-        helicopter_tail_rotor_node.reference_point = glm::vec3(-0.35, -2.3, -10.4);
-        
+        terrain_node.name = "terrain".to_string();
+
         parent_node.add_child(&terrain_node);
-        terrain_node.add_child(&helicopter_body_node);
-        helicopter_body_node.add_child(&helicopter_door_node);
-        helicopter_body_node.add_child(&helicopter_main_rotor_node);
-        helicopter_body_node.add_child(&helicopter_tail_rotor_node);
+
+        let before = Instant::now();
+        let terrain_tiles = lod::split_into_tiles(&lunarsurface, TERRAIN_TILES, TERRAIN_TILES);
+        // Generated terrain has no file to keep its levels of detail next to
+        let terrain_source = if terrain_seed.is_none() { Some(terrain_path.as_str()) } else { None };
+        let tile_lods = lod::generate_tile_lods(&terrain_tiles, &lod::DEFAULT_LOD_RATIOS[1..], terrain_source);
+        for (i, (tile, coarser)) in terrain_tiles.iter().zip(tile_lods.iter()).enumerate() {
+            let vao_id = unsafe { create_vao(&tile.vertices, &tile.indices, &tile.colors, &tile.normals) };
+            let mut tile_node = SceneNode::from_vao(vao_id, tile.index_count);
+            tile_node.name = format!("terrain_tile_{}", i);
+            tile_node.attach_mesh(tile);
+            tile_node.lods = unsafe { create_lod_levels(vao_id, tile.index_count, coarser) };
+            terrain_node.add_child(&tile_node);
+        }
+        println!("Built terrain levels of detail in {:.3}ms.", Instant::now().duration_since(before).as_micros() as f32 / 1e3);
+        terrain_node.add_child(&loaded_scene.root);
+
+        // The nodes main moves around itself. They live in the loaded scene for as long as it does,
+        // which is as long as this thread.
+        let root = &mut **loaded_scene.root as *mut SceneNode;
+        let helicopter_body_node = unsafe { &mut *root }.find("helicopter_body").expect("The scene has no helicopter_body") as *mut SceneNode;
+        let helicopter_body_node = unsafe { &mut *helicopter_body_node };
+        let escort_body_node = unsafe { &mut *root }.find("escort_body").expect("The scene has no escort_body") as *mut SceneNode;
+        let escort_body_node = unsafe { &mut *escort_body_node };
+
+        // Every helicopter shares the levels of detail of the body
+        if let Some(body) = loaded_scene.meshes.get("body") {
+            let body_lods = lod::generate_lods(body, &lod::DEFAULT_LOD_RATIOS[1..]);
+            let lods = unsafe { create_lod_levels(loaded_scene.vaos["body"], body.index_count, &body_lods) };
+            share_lod_levels(unsafe { &mut *root }, body, &lods);
+        }
+
+        // The fleet is parked on the ground, wherever the scene puts it
+        if let Some(fleet) = unsafe { &mut *root }.find("fleet") {
+            for &fleet_body_node in &fleet.children {
+                let fleet_body_node = unsafe { &mut *fleet_body_node };
+                let (x, z) = (fleet_body_node.position.x, fleet_body_node.position.z);
+                fleet_body_node.position.y = terrain_collider.height_at(x, z).unwrap_or(0.0);
+            }
+        }
+
+        let mut escort_route = PathFollower::new(Path::catmull_rom(vec![
+            glm::vec3(-40.0, 12.0, -20.0),
+            glm::vec3(  0.0, 18.0, -50.0),
+            glm::vec3( 40.0, 10.0, -20.0),
+            glm::vec3( 30.0, 14.0,  30.0),
+            glm::vec3(-30.0, 20.0,  40.0),
+        ], true), 12.0);
         
         //helicopter_body_node.position = glm::vec3(0.0, 0.0, -20.0);
+
+        // == // Set up your animations here
+        let mut animation_player = AnimationPlayer::new();
+
+        // The rotors, doors and whatever else the scene animates
+        for animation in loaded_scene.animations.drain(..) {
+            animation_player.play(animation);
+        }
+        // The rotors of the player's helicopter spin with the engine
+        let rotor_spin_handles: Vec<usize> = ["helicopter_main_rotor_spin", "helicopter_tail_rotor_spin"].iter()
+            .filter_map(|name| animation_player.find(name))
+            .collect();
+
+        // As does a glTF asset, kept around for as long as its nodes point into it
+        let gltf_asset = gltf_path.as_ref().map(|path| {
+            let mut asset = GltfAsset::load(path).expect("Failed to load glTF file");
+            asset.upload(&|mesh| unsafe {
+                let vao_id = create_vao(&mesh.vertices, &mesh.indices, &mesh.colors, &mesh.normals);
+                add_uvs(vao_id, &mesh.uvs);
+                vao_id
+            });
+            let mut imported = asset.instantiate("gltf");
+            parent_node.add_child(&imported.root);
+            for animation in imported.animations.drain(..) {
+                animation_player.play(animation);
+            }
+            asset
+        });
+
+        // The helicopter flies on autopilot until the player takes over
+        let mut autopilot = true;
+        let mut flight_controls = FlightControls::new();
+        let mut flight_model = FlightModel::new(glm::zero(), glm::quat_identity());
+
         // == // Set up your shaders here
 
         // Basic usage of shader helper:
@@ -262,12 +463,92 @@ fn main() {
             shader::ShaderBuilder::new()
                 .attach_file("./shaders/simple.vert")
                 .attach_file("./shaders/simple.frag")
+                .attach_file("./shaders/lighting.frag")
                 .link()
         };
 
+        // Nodes sharing a mesh can be drawn together in one instanced draw call
+        let mut instancing = true;
+        let instanced_shader = unsafe {
+            shader::ShaderBuilder::new()
+                .attach_file("./shaders/instanced.vert")
+                .attach_file("./shaders/simple.frag")
+                .attach_file("./shaders/lighting.frag")
+                .link()
+        };
+        let mut batcher = Batcher::new();
+
+        // The camera matrices are shared by both shaders through a uniform buffer, filled once a frame
+        let mut camera_uniforms = unsafe { UniformBuffer::new(uniform_buffer::camera_block(), uniform_buffer::CAMERA_BINDING) };
+        for shader in [&simple_shader, &instanced_shader] {
+            if let Err(e) = unsafe { camera_uniforms.validate(shader) } {
+                panic!("{}", e);
+            }
+        }
+
+        // The scene is drawn off-screen, and put on screen through a chain of post-processing effects
+        let framebuffer_samples = unsafe { render_config.supported_framebuffer_samples() };
+        let mut post_chain = unsafe { PostChain::new(window_width, window_height, framebuffer_samples) };
+        post_chain.settings.anti_aliasing = render_config.anti_aliasing;
+
+        // Deferred shading, which can be swapped with forward rendering to compare them
+        let mut deferred = false;
+        let mut deferred_renderer = unsafe { DeferredRenderer::new(window_width, window_height) };
+        // A cubemap for the background if there is one, or else stars and the Earth
+        let mut skybox = unsafe {
+            skybox::Skybox::new(Some(&skybox_path), &|mesh| create_vao(&mesh.vertices, &mesh.indices, &mesh.colors, &mesh.normals))
+        };
+        let [cubemap_shader, starfield_shader] = skybox.shaders();
+        // See-through nodes, drawn after everything else
+        let mut transparency_pass = unsafe { TransparencyPass::new(window_width, window_height) };
+        let [oit_shader] = transparency_pass.shaders();
+        // Wireframe, normals and bounding boxes, for when a model looks wrong
+        let mut debug_views = unsafe { DebugViews::new() };
+        // Lines and shapes asked for from anywhere with the `debug_draw` functions
+        let mut debug_draw = unsafe { DebugDraw::new() };
+        // Whether to show where the helicopters are headed and the escort's route
+        let mut show_routes = false;
+        // Whether to print the labels of the waypoints once they have been drawn
+        let mut print_labels = false;
+
+        for shader in [&deferred_renderer.geometry_shader, &deferred_renderer.instanced_geometry_shader, &deferred_renderer.point_light_shader, &post_chain.ssao.shader, cubemap_shader, starfield_shader, oit_shader, &debug_draw.shader] {
+            if let Err(e) = unsafe { camera_uniforms.validate(shader) } {
+                panic!("{}", e);
+            }
+        }
+        for shader in debug_views.shaders() {
+            if let Err(e) = unsafe { camera_uniforms.validate(shader) } {
+                panic!("{}", e);
+            }
+        }
+
+        // Lights scattered over the ground, and one under the helicopter
+        let mut point_lights = vec![lights::PointLight { position: glm::vec3(0.0, 0.0, 0.0), color: glm::vec3(1.0, 0.9, 0.7) * 3.0, radius: 25.0 }];
+        point_lights.extend(lights::scatter(&terrain_collider, lights::MAX_LIGHTS - 1, glm::vec2(0.0, 0.0), 150.0, 7));
+        let mut light_uniforms = unsafe { UniformBuffer::new(lights::light_block(), lights::LIGHTS_BINDING) };
+        for shader in [&simple_shader, &instanced_shader, oit_shader] {
+            if let Err(e) = unsafe { light_uniforms.validate(shader) } {
+                panic!("{}", e);
+            }
+        }
+
+        // Picking by ray casting on the CPU, or by reading back node ids from the GPU
+        let mut gpu_picking = false;
+        let id_shader = unsafe {
+            shader::ShaderBuilder::new()
+                .attach_file("./shaders/id.vert")
+                .attach_file("./shaders/id.frag")
+                .link()
+        };
+        let mut id_buffer = unsafe { IdBuffer::new(INITIAL_SCREEN_W, INITIAL_SCREEN_H) };
+
         // The main rendering loop
         let first_frame_time = std::time::Instant::now();
         let mut previous_frame_time = first_frame_time;
+        let mut previous_keys = Vec::<VirtualKeyCode>::new();
+        let mut culling_stats = CullingStats::default();
+        let mut last_stats_report = first_frame_time;
+        let mut report_stats = report_stats;
         loop {
             // Compute time passed since the previous frame and since the start of the program
             let now = Instant::now();
@@ -280,14 +561,113 @@ fn main() {
                 if new_size.2 {
                     context.resize(glutin::dpi::PhysicalSize::new(new_size.0, new_size.1));
                     window_aspect_ratio = new_size.0 as f32 / new_size.1 as f32;
+                    window_width = new_size.0;
+                    window_height = new_size.1;
                     (*new_size).2 = false;
                     println!("Window was resized to {}x{}", new_size.0, new_size.1);
-                    unsafe { gl::Viewport(0, 0, new_size.0 as i32, new_size.1 as i32); }
+                    unsafe {
+                        gl::Viewport(0, 0, new_size.0 as i32, new_size.1 as i32);
+                        post_chain.resize(new_size.0, new_size.1);
+                        deferred_renderer.resize(new_size.0, new_size.1);
+                        transparency_pass.resize(new_size.0, new_size.1);
+                    }
                 }
             }
 
             // Handle keyboard input
+            flight_controls.center();
             if let Ok(keys) = pressed_keys.lock() {
+                // Keys that went down since the previous frame, for toggles
+                let just_pressed: Vec<VirtualKeyCode> = keys.iter().filter(|k| !previous_keys.contains(k)).cloned().collect();
+                previous_keys = keys.clone();
+
+                for key in just_pressed.iter() {
+                    match key {
+                        VirtualKeyCode::P => {
+                            autopilot = !autopilot;
+                            if !autopilot {
+                                // Take over from wherever the autopilot left the helicopter
+                                flight_model = FlightModel::new(helicopter_body_node.position, helicopter_body_node.rotation);
+                                flight_controls = FlightControls::new();
+                            }
+                            println!("Autopilot {}", if autopilot { "engaged" } else { "disengaged" });
+                        }
+                        VirtualKeyCode::F5 => {
+                            // Write the loaded scene back out, as it looks right now
+                            let snapshot_path = "./resources/scene_snapshot.ron";
+                            match loaded_scene.snapshot().save(snapshot_path) {
+                                Ok(()) => println!("Saved scene to {}", snapshot_path),
+                                Err(e) => println!("{}", e),
+                            }
+                        }
+                        VirtualKeyCode::M => {
+                            gpu_picking = !gpu_picking;
+                            println!("Picking on the {}", if gpu_picking { "GPU" } else { "CPU" });
+                        }
+                        VirtualKeyCode::B => {
+                            instancing = !instancing;
+                            println!("Instanced drawing {}", if instancing { "on" } else { "off" });
+                        }
+                        VirtualKeyCode::Key7 => {
+                            if skybox.has_cubemap() {
+                                skybox.procedural = !skybox.procedural;
+                            }
+                            println!("Background: {}", if skybox.procedural { "starfield" } else { "skybox" });
+                        }
+                        VirtualKeyCode::Key8 => {
+                            transparency_pass.mode = match transparency_pass.mode {
+                                TransparencyMode::Sorted => TransparencyMode::WeightedBlended,
+                                TransparencyMode::WeightedBlended => TransparencyMode::Sorted,
+                            };
+                            println!("Transparency: {:?}", transparency_pass.mode);
+                        }
+                        VirtualKeyCode::C => {
+                            deferred = !deferred;
+                            println!("{} shading", if deferred { "Deferred" } else { "Forward" });
+                        }
+                        // Toggling the debug views one by one
+                        VirtualKeyCode::F1 | VirtualKeyCode::F2 | VirtualKeyCode::F3 | VirtualKeyCode::F4 | VirtualKeyCode::F6 => {
+                            let (name, enabled) = match key {
+                                VirtualKeyCode::F1 => ("Wireframe", &mut debug_views.wireframe),
+                                VirtualKeyCode::F2 => ("Normal lines", &mut debug_views.normal_lines),
+                                VirtualKeyCode::F3 => ("Bounding boxes", &mut debug_views.bounding_boxes),
+                                VirtualKeyCode::F4 => ("Node axes", &mut debug_views.axes),
+                                _ => ("Normal shading", &mut debug_views.normal_shading),
+                            };
+                            *enabled = !*enabled;
+                            println!("{} {}", name, if *enabled { "on" } else { "off" });
+                        }
+                        VirtualKeyCode::F8 => {
+                            report_stats = !report_stats;
+                            println!("Culling stats {}", if report_stats { "on" } else { "off" });
+                        }
+                        VirtualKeyCode::F7 => {
+                            show_routes = !show_routes;
+                            print_labels = show_routes;
+                            println!("Routes {}", if show_routes { "on" } else { "off" });
+                        }
+                        // Toggling the post-processing effects one by one
+                        VirtualKeyCode::Key5 => {
+                            post_chain.settings.anti_aliasing = post_chain.settings.anti_aliasing.next();
+                            println!("Anti-aliasing: {:?}", post_chain.settings.anti_aliasing);
+                        }
+                        VirtualKeyCode::Key1 | VirtualKeyCode::Key2 | VirtualKeyCode::Key3 | VirtualKeyCode::Key4 | VirtualKeyCode::Key6 | VirtualKeyCode::N => {
+                            let settings = &mut post_chain.settings;
+                            let (name, enabled) = match key {
+                                VirtualKeyCode::Key1 => ("Bloom", &mut settings.bloom),
+                                VirtualKeyCode::Key2 => ("Tone mapping", &mut settings.tone_mapping),
+                                VirtualKeyCode::Key3 => ("Vignette", &mut settings.vignette),
+                                VirtualKeyCode::Key4 => ("Gamma correction", &mut settings.gamma_correction),
+                                VirtualKeyCode::Key6 => ("Ambient occlusion", &mut post_chain.ssao.settings.enabled),
+                                _ => ("Normal view", &mut settings.show_normals),
+                            };
+                            *enabled = !*enabled;
+                            println!("{} {}", name, if *enabled { "on" } else { "off" });
+                        }
+                        _ => { }
+                    }
+                }
+
                 for key in keys.iter() {
                     match key {
                         // The `VirtualKeyCode` enum is defined here:
@@ -323,6 +703,38 @@ fn main() {
                         VirtualKeyCode::Left => {
                             yaw -= delta_time * 3.0;
                         }
+
+                        // Helicopter controls
+                        VirtualKeyCode::I => {
+                            flight_controls.cyclic_pitch = -1.0;
+                        }
+                        VirtualKeyCode::K => {
+                            flight_controls.cyclic_pitch = 1.0;
+                        }
+                        VirtualKeyCode::J => {
+                            flight_controls.cyclic_roll = 1.0;
+                        }
+                        VirtualKeyCode::L => {
+                            flight_controls.cyclic_roll = -1.0;
+                        }
+                        VirtualKeyCode::U => {
+                            flight_controls.pedal = 1.0;
+                        }
+                        VirtualKeyCode::O => {
+                            flight_controls.pedal = -1.0;
+                        }
+                        VirtualKeyCode::R => {
+                            flight_controls.adjust_collective(delta_time * flight::COLLECTIVE_RATE);
+                        }
+                        VirtualKeyCode::F => {
+                            flight_controls.adjust_collective(-delta_time * flight::COLLECTIVE_RATE);
+                        }
+                        VirtualKeyCode::T => {
+                            flight_controls.adjust_throttle(delta_time * flight::THROTTLE_RATE);
+                        }
+                        VirtualKeyCode::G => {
+                            flight_controls.adjust_throttle(-delta_time * flight::THROTTLE_RATE);
+                        }
                         // default handler:
                         _ => { }
                     }
@@ -339,9 +751,13 @@ fn main() {
                 *delta = (0.0, 0.0); // reset when done
             }
 
-            // == // Please compute camera transforms here (exercise 2 & 3)
-            let mut view_matrix: glm::Mat4 = glm::identity();
+            // Keep the camera above the ground. The view translates by camera_position, so the
+            // eye is really at -camera_position.
+            if let Some(ground) = terrain_collider.height_at(-camera_position.x, -camera_position.z) {
+                camera_position.y = camera_position.y.min(-(ground + camera_clearance));
+            }
 
+            // == // Please compute camera transforms here (exercise 2 & 3)
             let fovy = 45.0_f32.to_radians();
 
             let perspective_transform = glm::perspective(window_aspect_ratio, fovy, 0.1, 1000.0);
@@ -350,53 +766,220 @@ fn main() {
             let yaw_rotation = glm::rotation(yaw, &glm::vec3(0.0, 1.0, 0.0));
             let pitch_rotation = glm::rotation(pitch, &glm::vec3(1.0, 0.0, 0.0));
 
-            view_matrix = perspective_transform * pitch_rotation * yaw_rotation * view_matrix * position_transform;
+            let camera_view = pitch_rotation * yaw_rotation * position_transform;
+            let view_matrix = perspective_transform * camera_view;
+
+            camera_uniforms.set("view", &camera_view);
+            camera_uniforms.set("projection", &perspective_transform);
+            camera_uniforms.set("view_projection", &view_matrix);
+            camera_uniforms.set("camera_position", &-camera_position);
+            camera_uniforms.set("time", &elapsed);
+
+            // Updating the helicopter, either by the autopilot or the flight model
+            if autopilot {
+                let delta_pose = toolbox::simple_heading_animation(elapsed);
 
-            //Updating the rotors:
-            helicopter_main_rotor_node.rotation = glm::vec3(0.0, elapsed * 5.0, 0.0);
-            helicopter_tail_rotor_node.rotation = glm::vec3(elapsed * 5.0, 0.0 , 0.0);
+                let ground = terrain_collider.height_at(delta_pose.x, delta_pose.z).unwrap_or(f32::MIN);
+                let altitude = (ground + autopilot_clearance).max(0.0);
 
-            let delta_pose = toolbox::simple_heading_animation(elapsed);
+                helicopter_body_node.position = glm::vec3(delta_pose.x, altitude, delta_pose.z);
+                helicopter_body_node.set_euler_angles(glm::vec3(delta_pose.pitch, delta_pose.yaw, delta_pose.roll));
+                for &handle in &rotor_spin_handles {
+                    animation_player.set_speed(handle, 1.0);
+                }
+            } else {
+                flight_model.update(&flight_controls, delta_time);
+                if let Some(ground) = terrain_collider.height_at(flight_model.position.x, flight_model.position.z) {
+                    flight_model.collide_with_ground(ground, delta_time);
+                }
+
+                helicopter_body_node.position = flight_model.position;
+                helicopter_body_node.rotation = flight_model.orientation;
+                for &handle in &rotor_spin_handles {
+                    animation_player.set_speed(handle, flight_model.rotor_speed());
+                }
+            }
 
-            helicopter_body_node.position = glm::vec3(delta_pose.x, 0.0, delta_pose.z);
-            helicopter_body_node.rotation = glm::vec3(delta_pose.pitch, delta_pose.yaw, delta_pose.roll);
+            // Updating the rotors and the door:
+            animation_player.update(delta_time);
+
+            let escort_pose = escort_route.advance(delta_time);
+            escort_body_node.position = escort_route.position();
+            escort_body_node.set_euler_angles(glm::vec3(escort_pose.pitch, escort_pose.yaw, escort_pose.roll));
+
+            if show_routes {
+                let route_style = debug_draw::Style::new(glm::vec4(0.3, 0.9, 1.0, 1.0));
+                let route = &escort_route.path;
+                let step = route.length() / 100.0;
+                for i in 0..100 {
+                    debug_draw::line(route.position_at(i as f32 * step), route.position_at((i + 1) as f32 * step), route_style);
+                }
+                for (i, waypoint) in route.points.iter().enumerate() {
+                    debug_draw::text_anchor(*waypoint, &format!("waypoint {}", i), route_style);
+                }
+
+                // Both helicopters face along their local -Z
+                let heading_style = debug_draw::Style::new(glm::vec4(1.0, 0.5, 0.1, 1.0)).on_top();
+                for node in [&*helicopter_body_node, &*escort_body_node] {
+                    let forward = glm::quat_rotate_vec3(&node.rotation, &glm::vec3(0.0, 0.0, -1.0));
+                    debug_draw::arrow(node.position, node.position + forward * 8.0, heading_style);
+                }
+            }
+
+            point_lights[0].position = helicopter_body_node.position - glm::vec3(0.0, 2.0, 0.0);
+            lights::set_lights(&mut light_uniforms, &point_lights);
 
             unsafe {
-                simple_shader.activate();
-                
-                // Clear the color and depth buffers
-                gl::ClearColor(0.035, 0.046, 0.078, 1.0); // night sky
-                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                camera_uniforms.upload();
+                light_uniforms.upload();
+
+                // Normal shading is done by the forward shaders, so it takes over from deferred shading
+                let deferred = deferred && !debug_views.normal_shading;
+
+                // Clear the color, normal and depth buffers
+                let sky_color = glm::vec4(0.035, 0.046, 0.078, 1.0); // night sky
+                if deferred {
+                    deferred_renderer.begin_geometry();
+                } else {
+                    post_chain.begin_scene(&sky_color);
+                }
+
+                // What drawing a node needs besides the node and the transformation of its parent
+                struct DrawContext<'a> {
+                    shader           : &'a shader::Shader,
+                    instanced_shader : Option<&'a shader::Shader>, // Batched up if there is one
+                    batcher          : &'a mut Batcher,
+                    transparency     : &'a mut TransparencyPass,
+                    frustum          : &'a Frustum,
+                    lod_view         : &'a LodView,
+                    stats            : &'a mut CullingStats,
+                }
 
-                unsafe fn draw_scene(node: &SceneNode, view_projection_matrix: &glm::Mat4, transformation_this_far: &glm::Mat4, shader: &shader::Shader) {
+                // With an instanced shader given, the nodes are queued up in the batcher instead of drawn.
+                // Transparent nodes are always queued up, to be drawn after everything opaque.
+                unsafe fn draw_scene(node: &mut SceneNode, transformation_this_far: &glm::Mat4, context: &mut DrawContext) {
                     let mut node_transformation = glm::identity::<f32, 4>();
 
                     let to_ref = glm::translation(&node.reference_point);
                     let from_ref = glm::translation(&-node.reference_point);
 
-                    let roll = glm::rotation(node.rotation.x, &glm::vec3(1.0, 0.0, 0.0));
-                    let pitch = glm::rotation(node.rotation.y, &glm::vec3(0.0, 1.0, 0.0));
-                    let yaw = glm::rotation(node.rotation.z, &glm::vec3(0.0, 0.0, 1.0));
+                    let rotation_transform = node.rotation_matrix();
 
                     let position_transform = glm::translation(&node.position);
                     let scale_transform = glm::scaling(&node.scale);
 
-                    node_transformation = position_transform * from_ref * scale_transform * pitch * roll * yaw * to_ref;
-                    
-                    if node.vao_id != 0 {
-                        shader.activate();
-                        gl::UniformMatrix4fv(shader.get_uniform_location("mvp_matrix"), 1, gl::FALSE, glm::value_ptr(&(view_projection_matrix*transformation_this_far*node_transformation)).as_ptr());
-                        gl::UniformMatrix4fv(shader.get_uniform_location("model_matrix"), 1, gl::FALSE, glm::value_ptr(&(transformation_this_far * node_transformation)).as_ptr());
-                        gl::BindVertexArray(node.vao_id);
-                        gl::DrawElements(gl::TRIANGLES, node.index_count, gl::UNSIGNED_INT, offset::<f32>(0));
+                    node_transformation = position_transform * from_ref * scale_transform * rotation_transform * to_ref;
+                    node.update_world_transform(&(transformation_this_far * node_transformation));
+
+                    // Nodes without bounds can't be culled, so they are always drawn
+                    let visible = node.world_bounds.is_none_or(|b| context.frustum.intersects(&b));
+                    if node.vao_id != 0 && !visible {
+                        context.stats.culled += 1;
+                    } else if node.vao_id != 0 {
+                        context.stats.drawn += 1;
+
+                        // Swap in a coarser mesh when the node appears small on screen
+                        let (vao_id, index_count) = match node.world_bounds.and_then(|b| context.lod_view.select(&node.lods, &b.sphere)) {
+                            Some(level) => (level.vao_id, level.index_count),
+                            None => (node.vao_id, node.index_count),
+                        };
+
+                        if node.is_transparent() {
+                            let center = node.world_bounds.map_or(node.world_transform.column(3).xyz(), |b| b.sphere.center);
+                            context.transparency.add(TransparentDraw { vao_id, index_count, model: node.world_transform, tint: node.tint, texture_id: node.texture_id, center });
+                        } else if let Some(instanced_shader) = context.instanced_shader {
+                            context.batcher.add(vao_id, index_count, node.texture_id, instanced_shader, Instance { model: node.world_transform, tint: node.tint });
+                        } else {
+                            let shader = context.shader;
+                            shader.activate();
+                            gl::UniformMatrix4fv(shader.get_uniform_location("model_matrix"), 1, gl::FALSE, glm::value_ptr(&(transformation_this_far * node_transformation)).as_ptr());
+                            gl::Uniform4fv(shader.get_uniform_location("tint"), 1, glm::value_ptr(&node.tint).as_ptr());
+                            gltf_import::bind_base_color(shader, node.texture_id);
+                            gl::BindVertexArray(vao_id);
+                            gl::DrawElements(gl::TRIANGLES, index_count, gl::UNSIGNED_INT, offset::<f32>(0));
+                            context.stats.draw_calls += 1;
+                        }
                     }
                     
                     for &child in &node.children {
-                        draw_scene(&*child, view_projection_matrix, &(transformation_this_far*node_transformation), shader);
+                        draw_scene(&mut *child, &(transformation_this_far*node_transformation), context);
                     }
                 }   
 
-                draw_scene(&parent_node, &view_matrix, &glm::identity(), &simple_shader);
+                let frustum = Frustum::from_matrix(&view_matrix);
+                let lod_view = LodView { eye: -camera_position, fovy };
+                culling_stats = CullingStats::default();
+                let (shader, instanced_shader) = if deferred {
+                    (&deferred_renderer.geometry_shader, &deferred_renderer.instanced_geometry_shader)
+                } else if debug_views.normal_shading {
+                    (&debug_views.normal_shader, &debug_views.instanced_normal_shader)
+                } else {
+                    (&simple_shader, &instanced_shader)
+                };
+                let instanced = if instancing { Some(instanced_shader) } else { None };
+                debug_views.begin_scene();
+                let mut context = DrawContext {
+                    shader,
+                    instanced_shader: instanced,
+                    batcher: &mut batcher,
+                    transparency: &mut transparency_pass,
+                    frustum: &frustum,
+                    lod_view: &lod_view,
+                    stats: &mut culling_stats,
+                };
+                draw_scene(&mut parent_node, &glm::identity(), &mut context);
+                culling_stats.draw_calls += batcher.flush();
+                debug_views.end_scene();
+
+                if deferred {
+                    deferred_renderer.light(&post_chain.scene, &sky_color, &point_lights);
+                }
+                skybox.draw();
+                let transparent_shader = if debug_views.normal_shading { &debug_views.normal_shader } else { &simple_shader };
+                debug_views.begin_scene();
+                culling_stats.draw_calls += transparency_pass.draw(transparent_shader, &camera_view, post_chain.scene_target());
+                debug_views.end_scene();
+                debug_views.draw(&parent_node, &frustum, post_chain.scene_target());
+                debug_draw.draw(delta_time, post_chain.scene_target());
+
+                post_chain.present(&perspective_transform);
+            }
+
+            // Report what was clicked, now that the world transformations are up to date
+            let click = mouse_click.lock().ok().and_then(|mut click| click.take());
+            if let Some((x, y)) = click {
+                let hit = if gpu_picking {
+                    unsafe {
+                        id_buffer.resize(window_width, window_height);
+                        id_buffer.draw(&parent_node, &view_matrix, &id_shader);
+                        id_buffer.pick(x, y, &view_matrix)
+                    }
+                } else {
+                    let ray = Ray::from_screen(x, y, window_width, window_height, &view_matrix);
+                    picking::pick(&parent_node, &ray)
+                };
+                match hit {
+                    Some(hit) => {
+                        debug_draw::point(hit.point, 10.0, debug_draw::Style::new(glm::vec4(1.0, 1.0, 1.0, 1.0)).on_top().lasting(2.0));
+                        let node = unsafe { &*hit.node };
+                        println!("Clicked {} at [{:.2}, {:.2}, {:.2}], {:.2} units away",
+                            node.name, hit.point.x, hit.point.y, hit.point.z, hit.distance);
+                    }
+                    None => println!("Clicked nothing"),
+                }
+            }
+
+            // There is no text on screen, so the labels are printed along with where they are
+            if print_labels {
+                for (label, x, y) in debug_draw.labels(&view_matrix, window_width, window_height) {
+                    println!("{} at ({:.0}, {:.0})", label, x, y);
+                }
+                print_labels = false;
+            }
+
+            if report_stats && now.duration_since(last_stats_report).as_secs_f32() >= 1.0 {
+                println!("Drew {} nodes in {} draw calls, culled {}", culling_stats.drawn, culling_stats.draw_calls, culling_stats.culled);
+                last_stats_report = now;
             }
 
             // Display the new color buffer on the display
@@ -422,6 +1005,9 @@ fn main() {
         }
     });
 
+    // Where the cursor was last seen inside the window, in pixels from the top left corner
+    let mut cursor_position = (0f32, 0f32);
+
     // Start the event loop -- This is where window events are initially handled
     el.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
@@ -470,6 +1056,15 @@ fn main() {
                     _      => { }
                 }
             }
+            Event::WindowEvent { event: WindowEvent::CursorMoved { position, .. }, .. } => {
+                cursor_position = (position.x as f32, position.y as f32);
+            }
+            Event::WindowEvent { event: WindowEvent::MouseInput { state: Pressed, button: MouseButton::Left, .. }, .. } => {
+                // Let the render thread pick whatever is under the cursor
+                if let Ok(mut click) = arc_mouse_click.lock() {
+                    *click = Some(cursor_position);
+                }
+            }
             Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => {
                 // Accumulate mouse movement
                 if let Ok(mut position) = arc_mouse_delta.lock() {
@@ -1,5 +1,16 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+
+use rand::Rng;
 use tobj;
 
+use crate::collision::TriangleGrid;
+use crate::bounds::Bounds;
+use crate::mesh_cache;
+use crate::noise;
+use crate::scene_graph::{Node, SceneNode};
+
 // internal helper
 fn generate_color_vec(color: [f32; 4], num: usize) -> Vec<f32> {
     color.iter().cloned().cycle().take(num*4).collect()
@@ -7,26 +18,108 @@ fn generate_color_vec(color: [f32; 4], num: usize) -> Vec<f32> {
 
 // Mesh
 
+#[derive(Clone)]
 pub struct Mesh {
     pub vertices    : Vec<f32>,
     pub normals     : Vec<f32>,
     pub colors      : Vec<f32>,
+    pub uvs         : Vec<f32>, // Texture coordinates, empty if the mesh has none
     pub indices     : Vec<u32>,
     pub index_count : i32,
+    pub bounds      : Bounds,
 }
 
 impl Mesh {
+    pub fn new(vertices: Vec<f32>, normals: Vec<f32>, colors: Vec<f32>, indices: Vec<u32>) -> Self {
+        let index_count = indices.len() as i32;
+        let bounds = Bounds::from_positions(&vertices);
+        Mesh {
+            vertices,
+            normals,
+            colors,
+            uvs: vec![],
+            indices,
+            index_count,
+            bounds,
+        }
+    }
+
+    // Uses the vertex colours of the file if it has them, and `color` otherwise
     pub fn from(mesh: tobj::Mesh, color: [f32; 4]) -> Self {
         let num_verts = mesh.positions.len() / 3;
+        let colors = if mesh.vertex_color.len() == num_verts * 3 {
+            mesh.vertex_color.chunks(3).flat_map(|c| vec![c[0], c[1], c[2], color[3]]).collect()
+        } else {
+            generate_color_vec(color, num_verts)
+        };
         let index_count = mesh.indices.len() as i32;
+        let bounds = Bounds::from_positions(&mesh.positions);
         Mesh {
             vertices: mesh.positions,
             normals: mesh.normals,
+            uvs: mesh.texcoords,
             indices: mesh.indices,
-            colors: generate_color_vec(color, num_verts),
+            colors,
             index_count,
+            bounds,
+        }
+    }
+
+    pub fn set_color(&mut self, color: [f32; 4]) {
+        self.colors = generate_color_vec(color, self.vertices.len() / 3);
+    }
+}
+
+// Per-vertex normals averaged from the triangles around each vertex, weighted by their area
+pub fn smooth_normals(vertices: &[f32], indices: &[u32]) -> Vec<f32> {
+    let mut normals = vec![0.0; vertices.len()];
+    for triangle in indices.chunks(3) {
+        let p = |i: usize| {
+            let v = triangle[i] as usize * 3;
+            glm::vec3(vertices[v], vertices[v + 1], vertices[v + 2])
+        };
+        let n = glm::cross(&(p(1) - p(0)), &(p(2) - p(0)));
+        for &i in triangle {
+            for k in 0..3 {
+                normals[i as usize * 3 + k] += n[k];
+            }
         }
     }
+    for n in normals.chunks_mut(3) {
+        let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        if length > 0.0 {
+            n.iter_mut().for_each(|x| *x /= length);
+        }
+    }
+    normals
+}
+
+// Loads every object in the OBJ file, triangulated and with a single index per vertex
+pub fn load_obj_models(path: &str) -> Result<Vec<tobj::Model>, String> {
+    let (models, _materials)
+        = tobj::load_obj(path,
+            &tobj::LoadOptions{
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            }
+        ).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+    Ok(models)
+}
+
+// Every object in the OBJ file as a white mesh, read from the binary cache next to the file when
+// it is up to date, and written to it otherwise
+pub fn load_obj_meshes(path: &str) -> Result<Vec<(String, Mesh)>, String> {
+    if let Some(meshes) = mesh_cache::read(path) {
+        return Ok(meshes);
+    }
+    let meshes: Vec<(String, Mesh)> = load_obj_models(path)?.into_iter()
+        .map(|model| (model.name, Mesh::from(model.mesh, [1.0, 1.0, 1.0, 1.0])))
+        .collect();
+    if let Err(e) = mesh_cache::write(path, &meshes) {
+        println!("Couldn't cache {}: {}", path, e);
+    }
+    Ok(meshes)
 }
 
 // Lunar terrain
@@ -36,14 +129,7 @@ impl Terrain {
     pub fn load(path: &str) -> Mesh {
         println!("Loading terrain model from path: {}", path);
         let before = std::time::Instant::now();
-        let (models, _materials)
-            = tobj::load_obj(path,
-                &tobj::LoadOptions{
-                    triangulate: true,
-                    single_index: true,
-                    ..Default::default()
-                }
-            ).expect("Failed to load terrain model");
+        let mut models = load_obj_meshes(path).expect("Failed to load terrain model");
         let after = std::time::Instant::now();
         println!("Done in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
 
@@ -54,71 +140,311 @@ impl Terrain {
             // I'll leave that as an optional exercise. ;)
         }
 
-        let terrain = models[0].to_owned();
+        let (name, terrain) = models.remove(0);
         println!("Loaded {} with {} points and {} triangles.",
-            terrain.name,
-            terrain.mesh.positions.len() /3,
-            terrain.mesh.indices.len() / 3,
+            name,
+            terrain.vertices.len() /3,
+            terrain.indices.len() / 3,
         );
 
-        Mesh::from(terrain.mesh, [1.0, 1.0, 1.0, 1.0])
+        terrain
+    }
+
+    // Also builds a grid over the triangles, for asking how high the ground is
+    pub fn load_with_collider(path: &str) -> (Mesh, TriangleGrid) {
+        let mesh = Terrain::load(path);
+        let grid = Terrain::collider(&mesh);
+        (mesh, grid)
+    }
+
+    pub fn collider(mesh: &Mesh) -> TriangleGrid {
+        let before = std::time::Instant::now();
+        let grid = TriangleGrid::new(mesh);
+        let after = std::time::Instant::now();
+        println!("Built terrain collider in {:.3}ms.", after.duration_since(before).as_micros() as f32 / 1e3);
+        grid
+    }
+
+    // Terrain from a greyscale image, black being the lowest ground and white `height` above it.
+    // Every pixel becomes a vertex, spread out over `size` by `size` units.
+    pub fn from_heightmap(path: &str, size: f32, height: f32) -> Result<Mesh, String> {
+        println!("Loading heightmap from path: {}", path);
+        let image = image::open(path)
+            .map_err(|e| format!("Failed to load heightmap {}: {}", path, e))?
+            .to_luma16();
+        let (columns, rows) = (image.width() as usize, image.height() as usize);
+        if columns < 2 || rows < 2 {
+            return Err(format!("Heightmap {} is too small", path));
+        }
+        let heights: Vec<f32> = image.pixels().map(|p| p[0] as f32 / 65535.0 * height).collect();
+        let depth = size * (rows - 1) as f32 / (columns - 1) as f32;
+        Ok(heightfield(&heights, columns, rows, size, depth))
+    }
+
+    // Rolling hills from fractal noise, peppered with craters
+    pub fn generate(settings: &TerrainSettings) -> Mesh {
+        println!("Generating terrain with seed {}", settings.seed);
+        let before = std::time::Instant::now();
+        let n = settings.resolution.max(2);
+        let spacing = settings.size / (n - 1) as f32;
+        let perlin = noise::Perlin::new(settings.seed);
+
+        let mut heights = vec![0.0; n * n];
+        for j in 0..n {
+            for i in 0..n {
+                let (x, z) = (i as f32 * settings.frequency * spacing, j as f32 * settings.frequency * spacing);
+                let smooth = 0.5 + 0.5 * perlin.fbm(x, z, settings.octaves, settings.lacunarity, settings.gain);
+                let ridged = perlin.ridged(x + 101.3, z + 47.9, settings.octaves, settings.lacunarity, settings.gain);
+                heights[j * n + i] = settings.height * (smooth * (1.0 - settings.ridged) + ridged * settings.ridged);
+            }
+        }
+
+        let mut rng = noise::seeded_rng(settings.seed);
+        for _ in 0..settings.craters {
+            let radius = noise::power_law(&mut rng, settings.crater_radius.0, settings.crater_radius.1, 3.0);
+            let center = (rng.gen::<f32>() * settings.size, rng.gen::<f32>() * settings.size);
+            stamp_crater(&mut heights, n, spacing, center, radius, radius * settings.crater_depth);
+        }
+
+        let mesh = heightfield(&heights, n, n, settings.size, settings.size);
+        let after = std::time::Instant::now();
+        println!("Generated {} points and {} triangles in {:.3}ms.", n * n, mesh.indices.len() / 3, after.duration_since(before).as_micros() as f32 / 1e3);
+        mesh
     }
 }
 
+pub struct TerrainSettings {
+    pub seed          : u64,
+    pub size          : f32,        // Width and depth
+    pub resolution    : usize,      // Vertices along each side
+    pub height        : f32,        // Of the highest hills, before cratering
+    pub frequency     : f32,        // Hills per unit of length, in the coarsest octave
+    pub octaves       : u32,
+    pub lacunarity    : f32,        // How much finer each octave is than the last
+    pub gain          : f32,        // How much weaker each octave is than the last
+    pub ridged        : f32,        // [0, 1] How much of the ridged noise to mix in
+    pub craters       : u32,
+    pub crater_radius : (f32, f32), // Smallest and largest, small ones being the most common
+    pub crater_depth  : f32,        // Relative to the radius
+}
 
-// Helicopter
+impl TerrainSettings {
+    // Gentle hills and plenty of craters, about the size of the lunar surface model
+    pub fn new(seed: u64) -> Self {
+        TerrainSettings {
+            seed,
+            size          : 400.0,
+            resolution    : 257,
+            height        : 14.0,
+            frequency     : 1.0 / 120.0,
+            octaves       : 6,
+            lacunarity    : 2.0,
+            gain          : 0.5,
+            ridged        : 0.3,
+            craters       : 60,
+            crater_radius : (3.0, 40.0),
+            crater_depth  : 0.25,
+        }
+    }
+}
 
-pub struct Helicopter {
-    pub body       : Mesh,
-    pub door       : Mesh,
-    pub main_rotor : Mesh,
-    pub tail_rotor : Mesh,
+// A bowl with a raised rim around it, added to the heights
+fn stamp_crater(heights: &mut [f32], n: usize, spacing: f32, center: (f32, f32), radius: f32, depth: f32) {
+    let rim = depth * 0.35;
+    let reach = radius * 1.6;
+    let cell = |v: f32| (v / spacing).max(0.0).min((n - 1) as f32) as usize;
+    for j in cell(center.1 - reach)..=cell(center.1 + reach) {
+        for i in cell(center.0 - reach)..=cell(center.0 + reach) {
+            let (dx, dz) = (i as f32 * spacing - center.0, j as f32 * spacing - center.1);
+            let d = (dx * dx + dz * dz).sqrt() / radius;
+            heights[j * n + i] += if d < 1.0 {
+                depth * (d * d - 1.0) + rim
+            } else {
+                // Smoothly down from the rim to nothing
+                let t = ((d - 1.0) / 0.6).min(1.0);
+                rim * (1.0 - t * t * (3.0 - 2.0 * t))
+            };
+        }
+    }
 }
 
-// You can use square brackets to access the components of the helicopter, if you want to use loops!
-use std::ops::Index;
-impl Index<usize> for Helicopter {
-    type Output = Mesh;
-    fn index<'a>(&'a self, i: usize) -> &'a Mesh {
-        match i {
-            0 => &self.body,
-            1 => &self.main_rotor,
-            2 => &self.tail_rotor,
-            3 => &self.door,
-            _ => panic!("Invalid index, try [0,3]"),
+// A grid mesh over the heights, given row by row, centered on the origin. Rows go from +Z to -Z.
+fn heightfield(heights: &[f32], columns: usize, rows: usize, width: f32, depth: f32) -> Mesh {
+    let (dx, dz) = (width / (columns - 1) as f32, depth / (rows - 1) as f32);
+    let height = |i: usize, j: usize| heights[j * columns + i];
+
+    let (mut vertices, mut normals, mut uvs) = (vec![], vec![], vec![]);
+    for j in 0..rows {
+        for i in 0..columns {
+            vertices.extend(&[-width / 2.0 + i as f32 * dx, height(i, j), depth / 2.0 - j as f32 * dz]);
+            uvs.extend(&[i as f32 / (columns - 1) as f32, j as f32 / (rows - 1) as f32]);
+
+            // Central differences, one-sided at the edges. Z decreases along the rows.
+            let (left, right) = (i.saturating_sub(1), (i + 1).min(columns - 1));
+            let (back, front) = (j.saturating_sub(1), (j + 1).min(rows - 1));
+            let slope_x = (height(right, j) - height(left, j)) / ((right - left) as f32 * dx);
+            let slope_z = (height(i, back) - height(i, front)) / ((front - back) as f32 * dz);
+            let n = glm::normalize(&glm::vec3(-slope_x, 1.0, -slope_z));
+            normals.extend(&[n.x, n.y, n.z]);
         }
     }
+
+    let mut indices = vec![];
+    for j in 0..rows as u32 - 1 {
+        for i in 0..columns as u32 - 1 {
+            let a = j * columns as u32 + i;
+            let (b, c, d) = (a + 1, a + columns as u32 + 1, a + columns as u32);
+            indices.extend(&[a, b, c, a, c, d]);
+        }
+    }
+
+    let colors = generate_color_vec([1.0, 1.0, 1.0, 1.0], columns * rows);
+    let mut mesh = Mesh::new(vertices, normals, colors, indices);
+    mesh.uvs = uvs;
+    mesh
+}
+
+// Models made of named parts
+//
+// A `Model` holds every object of an OBJ file as a separate mesh, keyed by name. A `ModelSchema`
+// can be given to check that the parts we rely on are there, to colour them, to rename them, and
+// to say how they hang together when turned into scene nodes. The helicopter of the main scene
+// is not loaded this way, its parts are laid out in resources/main_scene.ron instead.
+
+pub struct PartSchema {
+    pub object          : &'static str,         // Name of the object in the OBJ file
+    pub name            : &'static str,         // What we call the part
+    pub required        : bool,
+    pub color           : [f32; 4],
+    pub parent          : Option<&'static str>, // Name of the part this one is attached to
+    pub reference_point : [f32; 3],             // What the part rotates and scales around
+}
+
+pub struct ModelSchema {
+    pub parts         : &'static [PartSchema],
+    pub default_color : [f32; 4], // For objects the schema doesn't mention
+}
+
+impl ModelSchema {
+    pub fn part(&self, name: &str) -> Option<&PartSchema> {
+        self.parts.iter().find(|p| p.name == name)
+    }
+}
+
+pub struct Model {
+    pub parts  : HashMap<String, Mesh>,
+    pub names  : Vec<String>,                 // Part names, in the order of the file
+    pub schema : Option<&'static ModelSchema>,
+    vaos       : HashMap<String, u32>,
 }
 
-impl Helicopter {
-    pub fn load(path: &str) -> Self {
-        println!("Loading helicopter model...");
+impl Model {
+    pub fn load(path: &str) -> Result<Self, String> {
+        Model::load_parts(path, None)
+    }
+
+    // Fails if a required part of the schema is missing from the file
+    pub fn load_with_schema(path: &str, schema: &'static ModelSchema) -> Result<Self, String> {
+        Model::load_parts(path, Some(schema))
+    }
+
+    fn load_parts(path: &str, schema: Option<&'static ModelSchema>) -> Result<Self, String> {
+        println!("Loading model from path: {}", path);
         let before = std::time::Instant::now();
-        let (models, _materials)
-            = tobj::load_obj(path,
-                &tobj::LoadOptions{
-                    triangulate: true,
-                    single_index: true,
-                    ..Default::default()
-                }
-            ).expect("Failed to load helicopter model");
+        let models = load_obj_meshes(path)?;
         let after = std::time::Instant::now();
         println!("Done in {:.3}ms!", after.duration_since(before).as_micros() as f32 / 1e3);
 
-        for model in &models {
-            println!("Loaded {} with {} points and {} triangles.", model.name, model.mesh.positions.len() / 3, model.mesh.indices.len() / 3);
+        if let Some(schema) = schema {
+            for part in schema.parts.iter().filter(|p| p.required) {
+                if !models.iter().any(|(object, _)| object == part.object) {
+                    return Err(format!("{} has no object named {}, needed for the {}", path, part.object, part.name));
+                }
+            }
         }
 
-        let body_model = models.iter().find(|m| m.name == "Body_body").expect("Incorrect model file!").to_owned();
-        let door_model = models.iter().find(|m| m.name == "Door_door").expect("Incorrect model file!").to_owned();
-        let main_rotor_model = models.iter().find(|m| m.name == "Main_Rotor_main_rotor").expect("Incorrect model file!").to_owned();
-        let tail_rotor_model = models.iter().find(|m| m.name == "Tail_Rotor_tail_rotor").expect("Incorrect model file!").to_owned();
+        let mut parts = HashMap::new();
+        let mut names = vec![];
+        for (object, mut mesh) in models {
+            println!("Loaded {} with {} points and {} triangles.", object, mesh.vertices.len() / 3, mesh.indices.len() / 3);
+            let part = schema.and_then(|s| s.parts.iter().find(|p| p.object == object));
+            let (name, color) = match (part, schema) {
+                (Some(part), _) => (part.name.to_string(), part.color),
+                (None, Some(schema)) => (object, schema.default_color),
+                (None, None) => (object, [1.0, 1.0, 1.0, 1.0]),
+            };
+            if parts.contains_key(&name) {
+                return Err(format!("{} has more than one object named {}", path, name));
+            }
+            mesh.set_color(color);
+            parts.insert(name.clone(), mesh);
+            names.push(name);
+        }
 
-        Helicopter {
-            body:       Mesh::from(body_model.mesh,         [0.3, 0.3, 0.3, 1.0]),
-            door:       Mesh::from(door_model.mesh,         [0.1, 0.1, 0.3, 1.0]),
-            main_rotor: Mesh::from(main_rotor_model.mesh,   [0.3, 0.1, 0.1, 1.0]),
-            tail_rotor: Mesh::from(tail_rotor_model.mesh,   [0.1, 0.3, 0.1, 1.0]),
+        Ok(Model { parts, names, schema, vaos: HashMap::new() })
+    }
+
+    pub fn part(&self, name: &str) -> Option<&Mesh> {
+        self.parts.get(name)
+    }
+
+    // Uploads every part to the GPU. `create_vao` returns the VAO id of a mesh.
+    pub fn upload(&mut self, create_vao: &dyn Fn(&Mesh) -> u32) {
+        for (name, mesh) in &self.parts {
+            self.vaos.insert(name.clone(), create_vao(mesh));
+        }
+    }
+
+    pub fn vao(&self, name: &str) -> Option<u32> {
+        self.vaos.get(name).cloned()
+    }
+
+    // Builds a scene node for every part, named `prefix` followed by the part name, arranged as
+    // the schema says. Parts without a parent hang below the returned node, which is the part
+    // itself if there is only one of them. Can be called again for another copy sharing the
+    // same VAOs, and the model must outlive the nodes.
+    pub fn instantiate(&self, prefix: &str) -> Node {
+        assert!(self.vaos.len() == self.parts.len(), "Upload the model before instantiating it");
+
+        let mut nodes = HashMap::new();
+        for name in &self.names {
+            let mesh = &self.parts[name];
+            let mut node = SceneNode::from_vao(self.vaos[name], mesh.index_count);
+            node.name = format!("{}{}", prefix, name);
+            node.attach_mesh(mesh);
+            if let Some(part) = self.schema.and_then(|s| s.part(name)) {
+                node.reference_point = glm::make_vec3(&part.reference_point);
+            }
+            nodes.insert(name.as_str(), node);
+        }
+
+        let parent_of = |name: &str| self.schema
+            .and_then(|s| s.part(name))
+            .and_then(|p| p.parent)
+            .filter(|parent| self.parts.contains_key(*parent));
+        let top: Vec<&str> = self.names.iter()
+            .map(|n| n.as_str())
+            .filter(|n| parent_of(n).is_none())
+            .collect();
+
+        // The nodes are never dropped, so pointers to them stay valid after the map is gone
+        let pointers: HashMap<&str, *mut SceneNode> = nodes.iter_mut()
+            .map(|(name, node)| (*name, &mut ***node as *mut SceneNode))
+            .collect();
+        for name in &self.names {
+            if let Some(parent) = parent_of(name) {
+                unsafe { (*pointers[parent]).add_child(&*pointers[name.as_str()]) };
+            }
+        }
+
+        if top.len() == 1 {
+            return nodes.remove(top[0]).unwrap();
+        }
+        let mut root = SceneNode::new();
+        root.name = prefix.trim_end_matches('_').to_string();
+        for name in top {
+            root.add_child(&nodes[name]);
         }
+        root
     }
 }
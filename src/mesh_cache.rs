@@ -0,0 +1,181 @@
+extern crate nalgebra_glm as glm;
+
+use std::time::UNIX_EPOCH;
+
+use crate::bounds::{Aabb, Bounds, BoundingSphere};
+use crate::mesh::Mesh;
+
+// Binary cache of parsed OBJ files.
+//
+// Parsing a big OBJ text file takes a while, so the first time a file is loaded its meshes are
+// written to `<file>.cache` next to it, and read from there afterwards. The cache remembers the
+// size and modification time of the file it was made from, and is ignored as soon as either
+// changes. All numbers are little-endian:
+//
+//     "GLMC", version, source size, source mtime (seconds, nanoseconds), mesh count
+//     per mesh: name, positions, normals, uvs, colours, indices, bounds
+//
+// Other meshes worked out from a file, like its levels of detail, can be kept the same way in a
+// cache file of their own with `read_cache` and `write_cache`.
+
+const MAGIC: &[u8; 4] = b"GLMC";
+const VERSION: u32 = 2;
+
+pub fn cache_path(path: &str) -> String {
+    format!("{}.cache", path)
+}
+
+// Size and modification time of the file, which the cache must match to be used
+fn source_stamp(path: &str) -> Option<(u64, u64, u32)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((metadata.len(), modified.as_secs(), modified.subsec_nanos()))
+}
+
+// The cached meshes of the file, if there is a cache and it is up to date
+pub fn read(path: &str) -> Option<Vec<(String, Mesh)>> {
+    read_cache(&cache_path(path), path)
+}
+
+pub fn write(path: &str, meshes: &[(String, Mesh)]) -> Result<(), String> {
+    write_cache(&cache_path(path), path, meshes)
+}
+
+// The meshes in the cache file, if it was made from the source file as it is now
+pub fn read_cache(cache: &str, path: &str) -> Option<Vec<(String, Mesh)>> {
+    let bytes = std::fs::read(cache).ok()?;
+    let mut reader = Reader { bytes: &bytes, position: 0 };
+
+    if reader.take(4)? != MAGIC || reader.u32()? != VERSION { return None }
+    let (size, seconds, nanos) = source_stamp(path)?;
+    if reader.u64()? != size || reader.u64()? != seconds || reader.u32()? != nanos { return None }
+
+    let count = reader.u32()?;
+    let mut meshes = vec![];
+    for _ in 0..count {
+        let name = String::from_utf8(reader.bytes()?.to_vec()).ok()?;
+        let vertices = reader.f32s()?;
+        let normals = reader.f32s()?;
+        let uvs = reader.f32s()?;
+        let colors = reader.f32s()?;
+        let indices = reader.u32s()?;
+        let f = reader.f32s()?;
+        if f.len() != 10 { return None }
+        let bounds = Bounds {
+            aabb: Aabb { min: glm::vec3(f[0], f[1], f[2]), max: glm::vec3(f[3], f[4], f[5]) },
+            sphere: BoundingSphere { center: glm::vec3(f[6], f[7], f[8]), radius: f[9] },
+        };
+        let index_count = indices.len() as i32;
+        meshes.push((name, Mesh { vertices, normals, colors, uvs, indices, index_count, bounds }));
+    }
+    Some(meshes)
+}
+
+pub fn write_cache(cache: &str, path: &str, meshes: &[(String, Mesh)]) -> Result<(), String> {
+    let (size, seconds, nanos) = source_stamp(path).ok_or(format!("Can't read the timestamp of {}", path))?;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&seconds.to_le_bytes());
+    out.extend_from_slice(&nanos.to_le_bytes());
+    out.extend_from_slice(&(meshes.len() as u32).to_le_bytes());
+    for (name, mesh) in meshes {
+        write_bytes(&mut out, name.as_bytes());
+        write_f32s(&mut out, &mesh.vertices);
+        write_f32s(&mut out, &mesh.normals);
+        write_f32s(&mut out, &mesh.uvs);
+        write_f32s(&mut out, &mesh.colors);
+        out.extend_from_slice(&(mesh.indices.len() as u32).to_le_bytes());
+        for i in &mesh.indices {
+            out.extend_from_slice(&i.to_le_bytes());
+        }
+        let (aabb, sphere) = (mesh.bounds.aabb, mesh.bounds.sphere);
+        write_f32s(&mut out, &[
+            aabb.min.x, aabb.min.y, aabb.min.z,
+            aabb.max.x, aabb.max.y, aabb.max.z,
+            sphere.center.x, sphere.center.y, sphere.center.z, sphere.radius,
+        ]);
+    }
+
+    std::fs::write(cache, out).map_err(|e| format!("Failed to write {}: {}", cache, e))
+}
+
+// Parses every OBJ file in the directory and below, writing caches for those without a fresh
+// one. Returns how many caches were written.
+pub fn build_all(directory: &str) -> Result<usize, String> {
+    let entries = std::fs::read_dir(directory).map_err(|e| format!("Failed to read {}: {}", directory, e))?;
+    let mut written = 0;
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        let path_str = path.to_string_lossy().to_string();
+        if path.is_dir() {
+            written += build_all(&path_str)?;
+        } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("obj")) {
+            if read(&path_str).is_some() {
+                println!("{} is up to date", cache_path(&path_str));
+                continue;
+            }
+            crate::mesh::load_obj_meshes(&path_str)?;
+            println!("Wrote {}", cache_path(&path_str));
+            written += 1;
+        }
+    }
+    Ok(written)
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn write_f32s(out: &mut Vec<u8>, values: &[f32]) {
+    out.extend_from_slice(&(values.len() as u32).to_le_bytes());
+    for v in values {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+// Reads values off the front of a byte slice, giving None once it runs out
+struct Reader<'a> {
+    bytes    : &'a [u8],
+    position : usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.position..self.position.checked_add(n)?)?;
+        self.position += n;
+        Some(slice)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.take(4)?);
+        Some(u32::from_le_bytes(b))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Some(u64::from_le_bytes(b))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let n = self.u32()? as usize;
+        self.take(n)
+    }
+
+    fn f32s(&mut self) -> Option<Vec<f32>> {
+        let n = self.u32()? as usize;
+        let raw = self.take(n.checked_mul(4)?)?;
+        Some(raw.chunks(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+    }
+
+    fn u32s(&mut self) -> Option<Vec<u32>> {
+        let n = self.u32()? as usize;
+        let raw = self.take(n.checked_mul(4)?)?;
+        Some(raw.chunks(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+    }
+}
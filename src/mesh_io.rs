@@ -0,0 +1,412 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::mesh::Mesh;
+
+// Writing meshes out for inspection in other tools, and reading back the PLY files we write.
+//
+// OBJ files get the colours as the widespread `v x y z r g b` extension, without alpha, which
+// `load_obj_meshes` reads back. PLY files get everything, with colours as bytes.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlyFormat {
+    Ascii,
+    Binary, // Little-endian
+}
+
+impl Mesh {
+    pub fn write_obj(&self, path: &str) -> Result<(), String> {
+        let error = |e: std::io::Error| format!("Failed to write {}: {}", path, e);
+        let mut out = BufWriter::new(File::create(path).map_err(error)?);
+
+        let vertex_count = self.vertices.len() / 3;
+        let has_colors = self.colors.len() >= vertex_count * 4;
+        let has_normals = self.normals.len() >= vertex_count * 3;
+        let has_uvs = self.uvs.len() >= vertex_count * 2;
+
+        writeln!(out, "# {} vertices, {} triangles", vertex_count, self.indices.len() / 3).map_err(error)?;
+        writeln!(out, "o mesh").map_err(error)?;
+        for v in 0..vertex_count {
+            let p = &self.vertices[3 * v..3 * v + 3];
+            if has_colors {
+                let c = &self.colors[4 * v..4 * v + 3];
+                writeln!(out, "v {} {} {} {} {} {}", p[0], p[1], p[2], c[0], c[1], c[2]).map_err(error)?;
+            } else {
+                writeln!(out, "v {} {} {}", p[0], p[1], p[2]).map_err(error)?;
+            }
+        }
+        if has_uvs {
+            for uv in self.uvs.chunks(2).take(vertex_count) {
+                writeln!(out, "vt {} {}", uv[0], uv[1]).map_err(error)?;
+            }
+        }
+        if has_normals {
+            for n in self.normals.chunks(3).take(vertex_count) {
+                writeln!(out, "vn {} {} {}", n[0], n[1], n[2]).map_err(error)?;
+            }
+        }
+
+        // OBJ counts from 1, and we use the same index for every attribute
+        for triangle in self.indices.chunks(3) {
+            let corner = |i: u32| match (has_uvs, has_normals) {
+                (true, true)   => format!("{0}/{0}/{0}", i + 1),
+                (true, false)  => format!("{0}/{0}", i + 1),
+                (false, true)  => format!("{0}//{0}", i + 1),
+                (false, false) => format!("{}", i + 1),
+            };
+            writeln!(out, "f {} {} {}", corner(triangle[0]), corner(triangle[1]), corner(triangle[2])).map_err(error)?;
+        }
+        out.flush().map_err(error)
+    }
+
+    pub fn write_ply(&self, path: &str, format: PlyFormat) -> Result<(), String> {
+        let error = |e: std::io::Error| format!("Failed to write {}: {}", path, e);
+        let mut out = BufWriter::new(File::create(path).map_err(error)?);
+
+        let vertex_count = self.vertices.len() / 3;
+        let has_colors = self.colors.len() >= vertex_count * 4;
+        let has_normals = self.normals.len() >= vertex_count * 3;
+        let has_uvs = self.uvs.len() >= vertex_count * 2;
+
+        writeln!(out, "ply").map_err(error)?;
+        writeln!(out, "format {} 1.0", match format {
+            PlyFormat::Ascii => "ascii",
+            PlyFormat::Binary => "binary_little_endian",
+        }).map_err(error)?;
+        writeln!(out, "element vertex {}", vertex_count).map_err(error)?;
+        let mut properties = vec!["x", "y", "z"];
+        if has_normals { properties.extend(&["nx", "ny", "nz"]) }
+        if has_uvs { properties.extend(&["s", "t"]) }
+        for name in &properties {
+            writeln!(out, "property float {}", name).map_err(error)?;
+        }
+        if has_colors {
+            for name in &["red", "green", "blue", "alpha"] {
+                writeln!(out, "property uchar {}", name).map_err(error)?;
+            }
+        }
+        writeln!(out, "element face {}", self.indices.len() / 3).map_err(error)?;
+        writeln!(out, "property list uchar uint vertex_indices").map_err(error)?;
+        writeln!(out, "end_header").map_err(error)?;
+
+        for v in 0..vertex_count {
+            let mut floats = self.vertices[3 * v..3 * v + 3].to_vec();
+            if has_normals { floats.extend_from_slice(&self.normals[3 * v..3 * v + 3]) }
+            if has_uvs { floats.extend_from_slice(&self.uvs[2 * v..2 * v + 2]) }
+            let bytes: Vec<u8> = if has_colors {
+                self.colors[4 * v..4 * v + 4].iter().map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8).collect()
+            } else {
+                vec![]
+            };
+
+            match format {
+                PlyFormat::Ascii => {
+                    let fields: Vec<String> = floats.iter().map(|f| f.to_string())
+                        .chain(bytes.iter().map(|b| b.to_string()))
+                        .collect();
+                    writeln!(out, "{}", fields.join(" ")).map_err(error)?;
+                }
+                PlyFormat::Binary => {
+                    for f in &floats {
+                        out.write_all(&f.to_le_bytes()).map_err(error)?;
+                    }
+                    out.write_all(&bytes).map_err(error)?;
+                }
+            }
+        }
+
+        for triangle in self.indices.chunks(3) {
+            match format {
+                PlyFormat::Ascii => writeln!(out, "3 {} {} {}", triangle[0], triangle[1], triangle[2]).map_err(error)?,
+                PlyFormat::Binary => {
+                    out.write_all(&[3u8]).map_err(error)?;
+                    for i in triangle {
+                        out.write_all(&i.to_le_bytes()).map_err(error)?;
+                    }
+                }
+            }
+        }
+        out.flush().map_err(error)
+    }
+
+    // Reads a PLY file with a vertex element (x, y, z and optionally nx, ny, nz, s, t and
+    // red, green, blue, alpha) and a face element of triangles, ASCII or little-endian binary
+    pub fn read_ply(path: &str) -> Result<Mesh, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let error = |message: &str| format!("Failed to read {}: {}", path, message);
+
+        // The header is plain text, ending with a line saying so, which may end in \r\n
+        let marker_end = bytes.windows(10).position(|w| w == b"end_header")
+            .ok_or(error("no end of header"))? + 10;
+        let header_end = match &bytes[marker_end..] {
+            [b'\r', b'\n', ..] => marker_end + 2,
+            [b'\n', ..] => marker_end + 1,
+            _ => return Err(error("no end of header")),
+        };
+        let header = String::from_utf8_lossy(&bytes[..header_end]);
+
+        let mut format = None;
+        let mut elements: Vec<PlyElement> = vec![];
+        for line in header.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["format", "ascii", _] => format = Some(PlyFormat::Ascii),
+                ["format", "binary_little_endian", _] => format = Some(PlyFormat::Binary),
+                ["format", other, _] => return Err(error(&format!("unsupported format {}", other))),
+                ["element", name, count] => elements.push(PlyElement {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| error("bad element count"))?,
+                    properties: vec![],
+                }),
+                ["property", "list", count_type, item_type, name] => elements.last_mut()
+                    .ok_or(error("property before element"))?
+                    .properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind: PlyType::parse(item_type).ok_or(error("unknown property type"))?,
+                        list_count: Some(PlyType::parse(count_type).ok_or(error("unknown property type"))?),
+                    }),
+                ["property", kind, name] => elements.last_mut()
+                    .ok_or(error("property before element"))?
+                    .properties.push(PlyProperty {
+                        name: name.to_string(),
+                        kind: PlyType::parse(kind).ok_or(error("unknown property type"))?,
+                        list_count: None,
+                    }),
+                _ => {}
+            }
+        }
+        let format = format.ok_or(error("no format"))?;
+
+        let mut values = PlyValues::new(&bytes[header_end..], format);
+        let (mut vertices, mut normals, mut uvs, mut colors, mut indices) = (vec![], vec![], vec![], vec![], vec![]);
+        for element in &elements {
+            for _ in 0..element.count {
+                let mut row: Vec<(&str, f64)> = vec![];
+                let mut list: Vec<f64> = vec![];
+                for property in &element.properties {
+                    match property.list_count {
+                        Some(count_type) => {
+                            let count = values.next(count_type).ok_or(error("truncated data"))? as usize;
+                            for _ in 0..count {
+                                list.push(values.next(property.kind).ok_or(error("truncated data"))?);
+                            }
+                        }
+                        None => row.push((&property.name, values.next(property.kind).ok_or(error("truncated data"))?)),
+                    }
+                }
+                let get = |name: &str| row.iter().find(|(n, _)| *n == name).map(|(_, v)| *v);
+
+                match element.name.as_str() {
+                    "vertex" => {
+                        for name in &["x", "y", "z"] {
+                            vertices.push(get(name).ok_or(error("vertex without a position"))? as f32);
+                        }
+                        if let (Some(x), Some(y), Some(z)) = (get("nx"), get("ny"), get("nz")) {
+                            normals.extend(&[x as f32, y as f32, z as f32]);
+                        }
+                        if let (Some(s), Some(t)) = (get("s"), get("t")) {
+                            uvs.extend(&[s as f32, t as f32]);
+                        }
+                        if let (Some(r), Some(g), Some(b)) = (get("red"), get("green"), get("blue")) {
+                            let a = get("alpha").unwrap_or(255.0);
+                            colors.extend([r, g, b, a].iter().map(|c| *c as f32 / 255.0));
+                        }
+                    }
+                    "face" => {
+                        // Fan out anything bigger than a triangle
+                        for i in 1..list.len().saturating_sub(1) {
+                            indices.extend(&[list[0] as u32, list[i] as u32, list[i + 1] as u32]);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let vertex_count = vertices.len() / 3;
+        if colors.is_empty() {
+            colors = vec![1.0; vertex_count * 4];
+        }
+        let mut mesh = Mesh::new(vertices, normals, colors, indices);
+        mesh.uvs = uvs;
+        Ok(mesh)
+    }
+}
+
+struct PlyElement {
+    name       : String,
+    count      : usize,
+    properties : Vec<PlyProperty>,
+}
+
+struct PlyProperty {
+    name       : String,
+    kind       : PlyType,
+    list_count : Option<PlyType>, // Type of the length of a list property
+}
+
+#[derive(Clone, Copy)]
+enum PlyType { I8, U8, I16, U16, I32, U32, F32, F64 }
+
+impl PlyType {
+    fn parse(name: &str) -> Option<PlyType> {
+        Some(match name {
+            "char" | "int8" => PlyType::I8,
+            "uchar" | "uint8" => PlyType::U8,
+            "short" | "int16" => PlyType::I16,
+            "ushort" | "uint16" => PlyType::U16,
+            "int" | "int32" => PlyType::I32,
+            "uint" | "uint32" => PlyType::U32,
+            "float" | "float32" => PlyType::F32,
+            "double" | "float64" => PlyType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            PlyType::I8 | PlyType::U8 => 1,
+            PlyType::I16 | PlyType::U16 => 2,
+            PlyType::I32 | PlyType::U32 | PlyType::F32 => 4,
+            PlyType::F64 => 8,
+        }
+    }
+}
+
+// The body of a PLY file, read one value at a time
+struct PlyValues<'a> {
+    bytes    : &'a [u8],
+    position : usize,
+    format   : PlyFormat,
+}
+
+impl<'a> PlyValues<'a> {
+    fn new(bytes: &'a [u8], format: PlyFormat) -> Self {
+        PlyValues { bytes, position: 0, format }
+    }
+
+    fn next(&mut self, kind: PlyType) -> Option<f64> {
+        match self.format {
+            PlyFormat::Ascii => {
+                let rest = &self.bytes[self.position..];
+                let start = rest.iter().position(|b| !b.is_ascii_whitespace())?;
+                let length = rest[start..].iter().position(|b| b.is_ascii_whitespace()).unwrap_or(rest.len() - start);
+                self.position += start + length;
+                std::str::from_utf8(&rest[start..start + length]).ok()?.parse().ok()
+            }
+            PlyFormat::Binary => {
+                let b = self.bytes.get(self.position..self.position + kind.size())?;
+                self.position += kind.size();
+                Some(match kind {
+                    PlyType::I8  => b[0] as i8 as f64,
+                    PlyType::U8  => b[0] as f64,
+                    PlyType::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    PlyType::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    PlyType::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    PlyType::F64 => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh;
+
+    // A quad of two triangles, with a normal, a colour and texture coordinates per corner
+    fn quad() -> Mesh {
+        let vertices = vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, -1.0, 0.0, 0.5, -1.0];
+        let normals = vec![0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.6, 0.8, 0.0, 0.8, 0.6];
+        let colors = vec![1.0, 0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.2, 0.4, 0.6, 1.0];
+        let mut mesh = Mesh::new(vertices, normals, colors, vec![0, 1, 2, 0, 2, 3]);
+        mesh.uvs = vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0, 0.0, 1.0];
+        mesh
+    }
+
+    // A directory of its own for every test, as they run in parallel, removed when the test ends
+    struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("mesh_io_{}_{}", test, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn path(&self, file: &str) -> String {
+            self.0.join(file).to_str().unwrap().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn assert_close(read: &[f32], written: &[f32], tolerance: f32) {
+        assert_eq!(read.len(), written.len());
+        for (r, w) in read.iter().zip(written) {
+            assert!((r - w).abs() <= tolerance, "read {:?}, wrote {:?}", read, written);
+        }
+    }
+
+    fn assert_round_trip(read: &Mesh, written: &Mesh, color_tolerance: f32) {
+        assert_eq!(read.vertices, written.vertices);
+        assert_eq!(read.normals, written.normals);
+        assert_close(&read.colors, &written.colors, color_tolerance);
+        assert_eq!(read.indices, written.indices);
+    }
+
+    #[test]
+    fn obj_round_trip() {
+        let dir = TempDir::new("obj");
+        let path = dir.path("quad.obj");
+        let written = quad();
+        written.write_obj(&path).unwrap();
+        // Without the cache, which would be left next to the file
+        let mut models = mesh::load_obj_models(&path).unwrap();
+        assert_eq!(models.len(), 1);
+        let read = Mesh::from(models.remove(0).mesh, [1.0; 4]);
+        assert_round_trip(&read, &written, 0.0);
+        assert_eq!(read.uvs, written.uvs);
+    }
+
+    #[test]
+    fn ascii_ply_round_trip() {
+        let dir = TempDir::new("ascii_ply");
+        let path = dir.path("quad.ply");
+        let written = quad();
+        written.write_ply(&path, PlyFormat::Ascii).unwrap();
+        let read = Mesh::read_ply(&path).unwrap();
+        assert_round_trip(&read, &written, 0.5 / 255.0);
+        assert_eq!(read.uvs, written.uvs);
+    }
+
+    #[test]
+    fn binary_ply_round_trip() {
+        let dir = TempDir::new("binary_ply");
+        let path = dir.path("quad.ply");
+        let written = quad();
+        written.write_ply(&path, PlyFormat::Binary).unwrap();
+        let read = Mesh::read_ply(&path).unwrap();
+        assert_round_trip(&read, &written, 0.5 / 255.0);
+        assert_eq!(read.uvs, written.uvs);
+    }
+
+    #[test]
+    fn ply_with_crlf_header() {
+        let dir = TempDir::new("crlf_ply");
+        let path = dir.path("triangle.ply");
+        let header = "ply\r\nformat ascii 1.0\r\nelement vertex 3\r\nproperty float x\r\nproperty float y\r\n\
+                      property float z\r\nelement face 1\r\nproperty list uchar uint vertex_indices\r\nend_header\r\n";
+        std::fs::write(&path, format!("{}0 0 0\r\n1 0 0\r\n0 1 0\r\n3 0 1 2\r\n", header)).unwrap();
+        let read = Mesh::read_ply(&path).unwrap();
+        assert_eq!(read.vertices, vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(read.indices, vec![0, 1, 2]);
+    }
+}
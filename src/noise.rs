@@ -0,0 +1,99 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+// Seeded 2D gradient noise (Perlin's improved noise) and fractal sums of it.
+
+pub struct Perlin {
+    permutation: [u8; 512], // Shuffled 0..256, twice over so lookups never wrap
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut values: Vec<u8> = (0..=255).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(seed));
+        let mut permutation = [0; 512];
+        for i in 0..512 {
+            permutation[i] = values[i % 256];
+        }
+        Perlin { permutation }
+    }
+
+    // Smooth noise in roughly [-1, 1], zero at every integer point
+    pub fn noise(&self, x: f32, y: f32) -> f32 {
+        let (xf, yf) = (x.floor(), y.floor());
+        let (xi, yi) = ((xf as i32 & 255) as usize, (yf as i32 & 255) as usize);
+        let (x, y) = (x - xf, y - yf);
+        let (u, v) = (fade(x), fade(y));
+
+        let p = &self.permutation;
+        let aa = p[p[xi] as usize + yi];
+        let ab = p[p[xi] as usize + yi + 1];
+        let ba = p[p[xi + 1] as usize + yi];
+        let bb = p[p[xi + 1] as usize + yi + 1];
+
+        lerp(v,
+            lerp(u, gradient(aa, x, y), gradient(ba, x - 1.0, y)),
+            lerp(u, gradient(ab, x, y - 1.0), gradient(bb, x - 1.0, y - 1.0)),
+        )
+    }
+
+    // Fractal Brownian motion: octaves of noise, each `lacunarity` times finer and `gain` times
+    // weaker than the last. Roughly in [-1, 1].
+    pub fn fbm(&self, x: f32, y: f32, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+        let (mut sum, mut amplitude, mut frequency, mut total) = (0.0, 1.0, 1.0, 0.0);
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(x * frequency, y * frequency);
+            total += amplitude;
+            amplitude *= gain;
+            frequency *= lacunarity;
+        }
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+
+    // Like `fbm`, but folding every octave into sharp ridges. In [0, 1].
+    pub fn ridged(&self, x: f32, y: f32, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+        let (mut sum, mut amplitude, mut frequency, mut total) = (0.0, 1.0, 1.0, 0.0);
+        for _ in 0..octaves {
+            let ridge = 1.0 - self.noise(x * frequency, y * frequency).abs();
+            sum += amplitude * ridge * ridge;
+            total += amplitude;
+            amplitude *= gain;
+            frequency *= lacunarity;
+        }
+        if total > 0.0 { sum / total } else { 0.0 }
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+// Dot product with one of eight gradient directions picked by the hash
+fn gradient(hash: u8, x: f32, y: f32) -> f32 {
+    match hash & 7 {
+        0 =>  x + y,
+        1 => -x + y,
+        2 =>  x - y,
+        3 => -x - y,
+        4 =>  x,
+        5 => -x,
+        6 =>  y,
+        _ => -y,
+    }
+}
+
+// A uniformly random number generator from a seed, for anything else that should come out the
+// same every time
+pub fn seeded_rng(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
+}
+
+// Number in [low, high) with small values much more likely, like the sizes of craters
+pub fn power_law(rng: &mut StdRng, low: f32, high: f32, exponent: f32) -> f32 {
+    low * (high / low).powf(rng.gen::<f32>().powf(exponent))
+}
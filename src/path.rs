@@ -0,0 +1,231 @@
+extern crate nalgebra_glm as glm;
+
+use crate::toolbox::Heading;
+
+// Spline paths for things to fly along.
+//
+// A path is a chain of cubic segments, parameterised by `u` in [0, segment_count]. Since equal
+// steps in `u` do not give equal steps along the curve, a lookup table of arc lengths is built
+// on construction, so that the path can be traversed by distance at constant speed.
+
+// How many samples per segment the arc length table is built from
+const SAMPLES_PER_SEGMENT: usize = 64;
+
+// Same constants as `toolbox::simple_heading_animation` uses, so paths look alike
+const PITCH_PER_SPEED: f32 = -0.175 * 0.05;
+const ROLL_PER_ACCELERATION: f32 = 0.02;
+const MAX_ROLL: f32 = 0.6; // measured in radians
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplineKind {
+    CatmullRom, // Passes through every waypoint
+    Bezier,     // Passes through every third point, the ones in between are control handles
+}
+
+pub struct Path {
+    pub kind   : SplineKind,
+    pub points : Vec<glm::Vec3>,
+    pub closed : bool,
+
+    arc_lengths : Vec<f32>, // Distance along the path at u = i / SAMPLES_PER_SEGMENT
+}
+
+impl Path {
+    // A smooth curve through all the waypoints. A closed path loops back to the first waypoint.
+    pub fn catmull_rom(waypoints: Vec<glm::Vec3>, closed: bool) -> Self {
+        assert!(waypoints.len() >= 2, "A path needs at least two waypoints");
+        Path::build(SplineKind::CatmullRom, waypoints, closed)
+    }
+
+    // Cubic Bézier segments on the form [p0, c0, c1, p1, c2, c3, p2, ...], with adjacent
+    // segments sharing their end points. A closed path wraps the last handles around to p0,
+    // so it takes 3n points instead of 3n + 1.
+    pub fn bezier(points: Vec<glm::Vec3>, closed: bool) -> Self {
+        let valid = if closed { points.len().is_multiple_of(3) } else { points.len() % 3 == 1 };
+        assert!(points.len() >= 3 && valid, "Invalid number of Bézier control points");
+        Path::build(SplineKind::Bezier, points, closed)
+    }
+
+    fn build(kind: SplineKind, points: Vec<glm::Vec3>, closed: bool) -> Self {
+        let mut path = Path { kind, points, closed, arc_lengths: vec![] };
+
+        let samples = path.segment_count() * SAMPLES_PER_SEGMENT;
+        let mut length = 0.0;
+        let mut previous = path.point(0.0);
+        path.arc_lengths.push(0.0);
+        for i in 1..=samples {
+            let current = path.point(i as f32 / SAMPLES_PER_SEGMENT as f32);
+            length += glm::distance(&previous, &current);
+            path.arc_lengths.push(length);
+            previous = current;
+        }
+        path
+    }
+
+    pub fn segment_count(&self) -> usize {
+        let n = self.points.len();
+        match (self.kind, self.closed) {
+            (SplineKind::CatmullRom, false) => n - 1,
+            (SplineKind::CatmullRom, true)  => n,
+            (SplineKind::Bezier, false)     => (n - 1) / 3,
+            (SplineKind::Bezier, true)      => n / 3,
+        }
+    }
+
+    pub fn length(&self) -> f32 {
+        *self.arc_lengths.last().unwrap()
+    }
+
+    // Control points of segment `i` as a cubic Bézier curve
+    fn segment(&self, i: usize) -> [glm::Vec3; 4] {
+        let n = self.points.len();
+        let p = |j: isize| -> glm::Vec3 {
+            if self.closed {
+                self.points[j.rem_euclid(n as isize) as usize]
+            } else {
+                self.points[j.max(0).min(n as isize - 1) as usize]
+            }
+        };
+        match self.kind {
+            SplineKind::Bezier => {
+                let j = 3 * i as isize;
+                [p(j), p(j + 1), p(j + 2), p(j + 3)]
+            }
+            SplineKind::CatmullRom => {
+                // Convert the Catmull-Rom segment into its equivalent Bézier form
+                let j = i as isize;
+                let (p0, p1, p2, p3) = (p(j - 1), p(j), p(j + 1), p(j + 2));
+                [p1, p1 + (p2 - p0) / 6.0, p2 - (p3 - p1) / 6.0, p2]
+            }
+        }
+    }
+
+    // Splits u into a segment index and the local parameter within that segment
+    fn locate(&self, u: f32) -> (usize, f32) {
+        let segments = self.segment_count();
+        let u = u.max(0.0).min(segments as f32);
+        let i = (u.floor() as usize).min(segments - 1);
+        (i, u - i as f32)
+    }
+
+    pub fn point(&self, u: f32) -> glm::Vec3 {
+        let (i, t) = self.locate(u);
+        let [b0, b1, b2, b3] = self.segment(i);
+        let s = 1.0 - t;
+        b0 * (s * s * s) + b1 * (3.0 * s * s * t) + b2 * (3.0 * s * t * t) + b3 * (t * t * t)
+    }
+
+    // First derivative with respect to u
+    pub fn tangent(&self, u: f32) -> glm::Vec3 {
+        let (i, t) = self.locate(u);
+        let [b0, b1, b2, b3] = self.segment(i);
+        let s = 1.0 - t;
+        (b1 - b0) * (3.0 * s * s) + (b2 - b1) * (6.0 * s * t) + (b3 - b2) * (3.0 * t * t)
+    }
+
+    // Second derivative with respect to u
+    pub fn acceleration(&self, u: f32) -> glm::Vec3 {
+        let (i, t) = self.locate(u);
+        let [b0, b1, b2, b3] = self.segment(i);
+        (b2 - b1 * 2.0 + b0) * (6.0 * (1.0 - t)) + (b3 - b2 * 2.0 + b1) * (6.0 * t)
+    }
+
+    // Finds u such that the distance along the path from the start is `distance`.
+    // Closed paths wrap around, open paths are clamped to their ends.
+    pub fn param_at_distance(&self, distance: f32) -> f32 {
+        // All points in the same place, so every distance is at the start
+        let length = self.length();
+        if length == 0.0 { return 0.0 }
+        let distance = if self.closed {
+            distance.rem_euclid(length)
+        } else {
+            distance.clamp(0.0, length)
+        };
+
+        // Binary search for the first sample past the distance, then interpolate linearly
+        let i = match self.arc_lengths.binary_search_by(|d| d.total_cmp(&distance)) {
+            Ok(i) => return i as f32 / SAMPLES_PER_SEGMENT as f32,
+            Err(i) => i.max(1).min(self.arc_lengths.len() - 1),
+        };
+        let (d0, d1) = (self.arc_lengths[i - 1], self.arc_lengths[i]);
+        let t = if d1 > d0 { (distance - d0) / (d1 - d0) } else { 0.0 };
+        (i as f32 - 1.0 + t) / SAMPLES_PER_SEGMENT as f32
+    }
+
+    pub fn position_at(&self, distance: f32) -> glm::Vec3 {
+        self.point(self.param_at_distance(distance))
+    }
+
+    // The pose of something flying along the path at the given speed. Yaw follows the tangent,
+    // pitch leans forward with the speed and roll banks into the curve.
+    pub fn heading_at(&self, distance: f32, speed: f32) -> Heading {
+        let u = self.param_at_distance(distance);
+        let position = self.point(u);
+        let d1 = self.tangent(u);
+        let d2 = self.acceleration(u);
+
+        // Signed curvature of the path projected onto the ground plane
+        let ground_speed = (d1.x * d1.x + d1.z * d1.z).sqrt();
+        let curvature = if ground_speed > 1e-6 {
+            (d1.x * d2.z - d1.z * d2.x) / (ground_speed * ground_speed * ground_speed)
+        } else {
+            0.0
+        };
+        let lateral_acceleration = speed * speed * curvature;
+
+        Heading {
+            x     : position.x,
+            z     : position.z,
+            roll  : (ROLL_PER_ACCELERATION * lateral_acceleration).clamp(-MAX_ROLL, MAX_ROLL),
+            pitch : PITCH_PER_SPEED * speed,
+            yaw   : std::f32::consts::PI + d1.x.atan2(d1.z),
+        }
+    }
+}
+
+// Something moving along a path at a constant speed
+pub struct PathFollower {
+    pub path     : Path,
+    pub speed    : f32, // measured in units per second
+    pub distance : f32, // How far along the path I have come
+}
+
+impl PathFollower {
+    pub fn new(path: Path, speed: f32) -> Self {
+        PathFollower { path, speed, distance: 0.0 }
+    }
+
+    pub fn advance(&mut self, delta_time: f32) -> Heading {
+        self.distance += self.speed * delta_time;
+        // A closed path with all points in the same place has nowhere to wrap around
+        let length = self.path.length();
+        if self.path.closed && length > 0.0 {
+            self.distance = self.distance.rem_euclid(length);
+        }
+        self.heading()
+    }
+
+    pub fn heading(&self) -> Heading {
+        self.path.heading_at(self.distance, self.speed)
+    }
+
+    pub fn position(&self) -> glm::Vec3 {
+        self.path.position_at(self.distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degenerate_closed_path() {
+        let point = glm::vec3(1.0, 2.0, 3.0);
+        let mut follower = PathFollower::new(Path::catmull_rom(vec![point, point, point], true), 10.0);
+        let heading = follower.advance(0.5);
+        assert!(follower.distance.is_finite());
+        assert!(heading.x.is_finite() && heading.z.is_finite());
+        assert!(heading.roll.is_finite() && heading.pitch.is_finite() && heading.yaw.is_finite());
+        assert_eq!(follower.position(), point);
+    }
+}
@@ -0,0 +1,219 @@
+extern crate nalgebra_glm as glm;
+
+use std::ptr;
+
+use crate::collision;
+use crate::scene_graph::SceneNode;
+use crate::shader::Shader;
+
+// Finding out which scene node is under the mouse cursor.
+//
+// Both approaches rely on the world transformations stored in the nodes while drawing, so pick
+// after the scene has been drawn at least once.
+//
+//  * `pick` casts a ray from the cursor into the scene graph, testing node bounds first and then
+//    the triangles of the CPU-side mesh.
+//  * `IdBuffer` draws every node with a unique id into an off-screen buffer and reads back the
+//    id under the cursor. It picks exactly what is visible, but stalls the GPU to do so.
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin    : glm::Vec3,
+    pub direction : glm::Vec3, // Normalized
+}
+
+impl Ray {
+    // The ray through pixel (x, y) of the window, counting from the top left corner
+    pub fn from_screen(x: f32, y: f32, width: u32, height: u32, view_projection: &glm::Mat4) -> Ray {
+        let inverse = glm::inverse(view_projection);
+        let ndc_x = 2.0 * x / width as f32 - 1.0;
+        let ndc_y = 1.0 - 2.0 * y / height as f32;
+        let unproject = |z: f32| {
+            let p = inverse * glm::vec4(ndc_x, ndc_y, z, 1.0);
+            glm::vec3(p.x, p.y, p.z) / p.w
+        };
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+        Ray { origin: near, direction: glm::normalize(&(far - near)) }
+    }
+
+    pub fn at(&self, distance: f32) -> glm::Vec3 {
+        self.origin + self.direction * distance
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PickHit {
+    pub node     : *mut SceneNode,
+    pub point    : glm::Vec3,      // In world space
+    pub distance : f32,            // From the ray origin
+}
+
+// The nearest node hit by the ray. Nodes with a mesh attached are tested triangle by triangle,
+// the rest only by their bounding box.
+pub fn pick(root: &SceneNode, ray: &Ray) -> Option<PickHit> {
+    let mut nearest = None;
+    pick_node(root, ray, &mut nearest);
+    nearest
+}
+
+fn pick_node(node: &SceneNode, ray: &Ray, nearest: &mut Option<PickHit>) {
+    if node.vao_id != 0 {
+        if let Some(bounds) = node.world_bounds {
+            let box_distance = bounds.aabb.intersect_ray(&ray.origin, &ray.direction);
+            let closer = |d: f32| nearest.is_none_or(|hit| d < hit.distance);
+            if box_distance.is_some_and(closer) {
+                let distance = match node.mesh() {
+                    Some(mesh) => {
+                        // Test in the node's own space. The transformation is affine, so the
+                        // distance along the ray stays the same.
+                        let inverse = glm::inverse(&node.world_transform);
+                        let o = inverse * glm::vec4(ray.origin.x, ray.origin.y, ray.origin.z, 1.0);
+                        let d = inverse * glm::vec4(ray.direction.x, ray.direction.y, ray.direction.z, 0.0);
+                        let (origin, direction) = (glm::vec4_to_vec3(&o), glm::vec4_to_vec3(&d));
+                        (0..mesh.indices.len() / 3)
+                            .filter_map(|i| collision::ray_triangle(&origin, &direction, &collision::mesh_triangle(mesh, i)))
+                            .fold(None, |best: Option<f32>, t| Some(best.map_or(t, |b| b.min(t))))
+                    }
+                    None => box_distance,
+                };
+                if let Some(distance) = distance.filter(|&d| closer(d)) {
+                    *nearest = Some(PickHit {
+                        node: node as *const SceneNode as *mut SceneNode,
+                        point: ray.at(distance),
+                        distance,
+                    });
+                }
+            }
+        }
+    }
+
+    for &child in &node.children {
+        pick_node(unsafe { &*child }, ray, nearest);
+    }
+}
+
+// Off-screen buffer of node ids, 0 meaning nothing
+pub struct IdBuffer {
+    framebuffer : u32,
+    id_texture  : u32,
+    depth       : u32,
+    width       : u32,
+    height      : u32,
+    nodes       : Vec<*mut SceneNode>, // Node with id i + 1, from the last time the ids were drawn
+}
+
+impl IdBuffer {
+    pub unsafe fn new(width: u32, height: u32) -> Self {
+        let mut buffer = IdBuffer {
+            framebuffer: 0,
+            id_texture: 0,
+            depth: 0,
+            width: 0,
+            height: 0,
+            nodes: vec![],
+        };
+        buffer.resize(width, height);
+        buffer
+    }
+
+    pub unsafe fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height { return }
+        self.delete();
+        self.width = width;
+        self.height = height;
+
+        gl::GenFramebuffers(1, &mut self.framebuffer);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+
+        gl::GenTextures(1, &mut self.id_texture);
+        gl::BindTexture(gl::TEXTURE_2D, self.id_texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::R32UI as i32, width as i32, height as i32, 0, gl::RED_INTEGER, gl::UNSIGNED_INT, ptr::null());
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, self.id_texture, 0);
+
+        gl::GenRenderbuffers(1, &mut self.depth);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth);
+        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, width as i32, height as i32);
+        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, self.depth);
+
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            panic!("Id buffer framebuffer is incomplete");
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    unsafe fn delete(&mut self) {
+        if self.framebuffer != 0 {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteTextures(1, &self.id_texture);
+            gl::DeleteRenderbuffers(1, &self.depth);
+        }
+    }
+
+    // Draws the ids of every node into the buffer, using the given id shader
+    pub unsafe fn draw(&mut self, root: &SceneNode, view_projection: &glm::Mat4, shader: &Shader) {
+        let mut viewport = [0i32; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        gl::Viewport(0, 0, self.width as i32, self.height as i32);
+        let clear_id = 0u32;
+        gl::ClearBufferuiv(gl::COLOR, 0, &clear_id);
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+        gl::Disable(gl::BLEND);
+
+        shader.activate();
+        self.nodes.clear();
+        self.draw_node(root, view_projection, shader);
+
+        gl::Enable(gl::BLEND);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+    }
+
+    unsafe fn draw_node(&mut self, node: &SceneNode, view_projection: &glm::Mat4, shader: &Shader) {
+        if node.vao_id != 0 {
+            self.nodes.push(node as *const SceneNode as *mut SceneNode);
+            gl::UniformMatrix4fv(shader.get_uniform_location("mvp_matrix"), 1, gl::FALSE, glm::value_ptr(&(view_projection * node.world_transform)).as_ptr());
+            gl::Uniform1ui(shader.get_uniform_location("node_id"), self.nodes.len() as u32);
+            gl::BindVertexArray(node.vao_id);
+            gl::DrawElements(gl::TRIANGLES, node.index_count, gl::UNSIGNED_INT, ptr::null());
+        }
+        for &child in &node.children {
+            self.draw_node(&*child, view_projection, shader);
+        }
+    }
+
+    // Reads back what was drawn under pixel (x, y), counting from the top left corner
+    pub unsafe fn pick(&self, x: f32, y: f32, view_projection: &glm::Mat4) -> Option<PickHit> {
+        let (px, py) = (x as i32, self.height as i32 - 1 - y as i32);
+        if px < 0 || py < 0 || px >= self.width as i32 || py >= self.height as i32 { return None }
+
+        let mut id = 0u32;
+        let mut depth = 0f32;
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+        gl::ReadPixels(px, py, 1, 1, gl::RED_INTEGER, gl::UNSIGNED_INT, &mut id as *mut u32 as *mut _);
+        gl::ReadPixels(px, py, 1, 1, gl::DEPTH_COMPONENT, gl::FLOAT, &mut depth as *mut f32 as *mut _);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        if id == 0 { return None }
+
+        // Unproject the depth back into the world to find the point hit
+        let ray = Ray::from_screen(x, y, self.width, self.height, view_projection);
+        let ndc = glm::vec4(
+            2.0 * x / self.width as f32 - 1.0,
+            1.0 - 2.0 * y / self.height as f32,
+            2.0 * depth - 1.0,
+            1.0,
+        );
+        let p = glm::inverse(view_projection) * ndc;
+        let point = glm::vec3(p.x, p.y, p.z) / p.w;
+
+        Some(PickHit {
+            node: self.nodes[id as usize - 1],
+            point,
+            distance: glm::distance(&ray.origin, &point),
+        })
+    }
+}
@@ -0,0 +1,364 @@
+extern crate nalgebra_glm as glm;
+
+use serde::{Serialize, Deserialize};
+
+use crate::framebuffer::Framebuffer;
+use crate::shader::{Shader, ShaderBuilder};
+use crate::ssao::Ssao;
+
+// Post-processing of the rendered scene.
+//
+// The scene is drawn into an HDR framebuffer with three targets: the colour from `output_col`,
+// the world space normal from `output_normal` and the ambient part of the colour from
+// `output_ambient`. `present` then runs the enabled effects over it in a chain of full-screen
+// passes, ping-ponging between two buffers, the last pass drawing to the window:
+//
+//     ambient occlusion -> bloom -> tone mapping -> vignette -> gamma correction -> FXAA or SMAA
+//
+// Ambient occlusion takes light away, so it goes before anything else. Bloom and tone mapping
+// work on HDR colour, so they come next. Anti-aliasing goes last, as it looks for edges in the
+// colours as they will be displayed.
+//
+// With MSAA, the scene is drawn into a multisampled copy of the scene buffer instead, which is
+// resolved into the scene buffer before the chain runs.
+
+// Anti-aliasing done as a post-processing pass
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AntiAliasing {
+    None,
+    Fxaa, // Fast approximate anti-aliasing, a single pass
+    Smaa, // Morphological anti-aliasing in SMAA's three passes, though with the blending
+          // weights worked out in the shader instead of looked up in SMAA's area texture
+}
+
+impl AntiAliasing {
+    pub fn next(&self) -> AntiAliasing {
+        match self {
+            AntiAliasing::None => AntiAliasing::Fxaa,
+            AntiAliasing::Fxaa => AntiAliasing::Smaa,
+            AntiAliasing::Smaa => AntiAliasing::None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PostSettings {
+    pub bloom             : bool,
+    pub bloom_threshold   : f32, // Brightness above which things glow
+    pub bloom_strength    : f32,
+    pub bloom_blur_passes : u32, // Each a horizontal and a vertical blur
+    pub tone_mapping      : bool,
+    pub exposure          : f32,
+    pub vignette          : bool,
+    pub vignette_strength : f32,
+    pub gamma_correction  : bool,
+    pub gamma             : f32,
+    pub anti_aliasing     : AntiAliasing,
+    pub show_normals      : bool, // Display the normal target instead of the scene
+}
+
+impl Default for PostSettings {
+    // The effects changing how the scene looks are off until turned on, so it looks like it did
+    // without post-processing. Its colours are already meant for display, so gamma correcting
+    // them again would wash them out.
+    fn default() -> Self {
+        PostSettings {
+            bloom: false,
+            bloom_threshold: 0.8,
+            bloom_strength: 0.6,
+            bloom_blur_passes: 3,
+            tone_mapping: false,
+            exposure: 1.0,
+            vignette: false,
+            vignette_strength: 0.4,
+            gamma_correction: false,
+            gamma: 2.2,
+            anti_aliasing: AntiAliasing::Fxaa,
+            show_normals: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Effect {
+    AmbientOcclusion,
+    Bloom,
+    ToneMapping,
+    Vignette,
+    Gamma,
+    Fxaa,
+    Smaa,
+}
+
+pub struct PostChain {
+    pub settings       : PostSettings,
+    pub scene          : Framebuffer,         // Colour, normals and ambient light, with depth
+    pub ssao           : Ssao,
+    multisampled       : Option<Framebuffer>, // Drawn into instead of the scene, with MSAA
+    resolve_pending    : bool,                // The scene was drawn multisampled this frame
+    ping_pong          : [Framebuffer; 2],    // Between the passes of the chain
+    bloom              : [Framebuffer; 2],    // At half resolution
+    smaa_edges         : Framebuffer,
+    smaa_weights       : Framebuffer,
+    width              : u32,
+    height             : u32,
+    empty_vao          : u32,                 // Full-screen triangles make their vertices up themselves
+    bright_shader      : Shader,
+    blur_shader        : Shader,
+    bloom_shader       : Shader,
+    tone_shader        : Shader,
+    vignette_shader    : Shader,
+    gamma_shader       : Shader,
+    fxaa_shader        : Shader,
+    smaa_edge_shader   : Shader,
+    smaa_weight_shader : Shader,
+    smaa_blend_shader  : Shader,
+    copy_shader        : Shader,
+    normals_shader     : Shader,
+    ao_shader          : Shader,
+}
+
+unsafe fn fullscreen_shader(fragment_path: &str) -> Shader {
+    ShaderBuilder::new()
+        .attach_file("./shaders/fullscreen.vert")
+        .attach_file(fragment_path)
+        .link()
+}
+
+impl PostChain {
+    // The scene is drawn with MSAA if given more than one sample
+    pub unsafe fn new(width: u32, height: u32, samples: u32) -> Self {
+        let mut empty_vao = 0;
+        gl::GenVertexArrays(1, &mut empty_vao);
+        let (half_width, half_height) = (width / 2, height / 2);
+
+        PostChain {
+            settings: PostSettings::default(),
+            scene: Framebuffer::new(width, height, &[gl::RGBA16F, gl::RGB16F, gl::RGB16F], true),
+            ssao: Ssao::new(width, height),
+            multisampled: if samples > 1 {
+                Some(Framebuffer::new_multisampled(width, height, &[gl::RGBA16F, gl::RGB16F, gl::RGB16F], true, samples))
+            } else {
+                None
+            },
+            resolve_pending: false,
+            ping_pong: [
+                Framebuffer::new(width, height, &[gl::RGBA16F], false),
+                Framebuffer::new(width, height, &[gl::RGBA16F], false),
+            ],
+            bloom: [
+                Framebuffer::new(half_width, half_height, &[gl::RGBA16F], false),
+                Framebuffer::new(half_width, half_height, &[gl::RGBA16F], false),
+            ],
+            smaa_edges: Framebuffer::new(width, height, &[gl::RG8], false),
+            smaa_weights: Framebuffer::new(width, height, &[gl::RG8], false),
+            width,
+            height,
+            empty_vao,
+            bright_shader: fullscreen_shader("./shaders/post_bright.frag"),
+            blur_shader: fullscreen_shader("./shaders/post_blur.frag"),
+            bloom_shader: fullscreen_shader("./shaders/post_bloom.frag"),
+            tone_shader: fullscreen_shader("./shaders/post_tone_map.frag"),
+            vignette_shader: fullscreen_shader("./shaders/post_vignette.frag"),
+            gamma_shader: fullscreen_shader("./shaders/post_gamma.frag"),
+            fxaa_shader: fullscreen_shader("./shaders/post_fxaa.frag"),
+            smaa_edge_shader: fullscreen_shader("./shaders/post_smaa_edges.frag"),
+            smaa_weight_shader: fullscreen_shader("./shaders/post_smaa_weights.frag"),
+            smaa_blend_shader: fullscreen_shader("./shaders/post_smaa_blend.frag"),
+            copy_shader: fullscreen_shader("./shaders/post_copy.frag"),
+            normals_shader: fullscreen_shader("./shaders/post_normals.frag"),
+            ao_shader: fullscreen_shader("./shaders/post_ao.frag"),
+        }
+    }
+
+    // Call from the resize handler, with the new size of the window
+    pub unsafe fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.scene.resize(width, height);
+        if let Some(multisampled) = self.multisampled.as_mut() {
+            multisampled.resize(width, height);
+        }
+        self.ssao.resize(width, height);
+        self.smaa_edges.resize(width, height);
+        self.smaa_weights.resize(width, height);
+        for buffer in self.ping_pong.iter_mut() {
+            buffer.resize(width, height);
+        }
+        for buffer in self.bloom.iter_mut() {
+            buffer.resize(width / 2, height / 2);
+        }
+    }
+
+    // Binds and clears the scene buffer, or its multisampled copy, ready for the scene to be drawn
+    pub unsafe fn begin_scene(&mut self, clear_color: &glm::Vec4) {
+        match &self.multisampled {
+            Some(multisampled) => multisampled.bind(),
+            None => self.scene.bind(),
+        }
+        self.resolve_pending = self.multisampled.is_some();
+        let nothing = [0.0f32; 4];
+        gl::ClearBufferfv(gl::COLOR, 0, clear_color.as_ptr());
+        gl::ClearBufferfv(gl::COLOR, 1, nothing.as_ptr());
+        gl::ClearBufferfv(gl::COLOR, 2, nothing.as_ptr());
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+    }
+
+    // Where the scene is being drawn this frame
+    pub fn scene_target(&self) -> &Framebuffer {
+        match &self.multisampled {
+            Some(multisampled) if self.resolve_pending => multisampled,
+            _ => &self.scene,
+        }
+    }
+
+    fn effects(&self) -> Vec<Effect> {
+        let s = &self.settings;
+        [
+            (self.ssao.settings.enabled, Effect::AmbientOcclusion),
+            (s.bloom, Effect::Bloom),
+            (s.tone_mapping, Effect::ToneMapping),
+            (s.vignette, Effect::Vignette),
+            (s.gamma_correction, Effect::Gamma),
+            (s.anti_aliasing == AntiAliasing::Fxaa, Effect::Fxaa),
+            (s.anti_aliasing == AntiAliasing::Smaa, Effect::Smaa),
+        ].iter().filter(|(enabled, _)| *enabled).map(|(_, effect)| *effect).collect()
+    }
+
+    // Runs the enabled effects over the scene and puts the result in the window. The projection
+    // is the one the scene was drawn with.
+    pub unsafe fn present(&mut self, projection: &glm::Mat4) {
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Disable(gl::BLEND);
+        gl::BindVertexArray(self.empty_vao);
+
+        if self.resolve_pending {
+            if let Some(multisampled) = &self.multisampled {
+                multisampled.resolve_into(&self.scene);
+            }
+            self.resolve_pending = false;
+        }
+
+        let effects = self.effects();
+        let occlusion = if effects.contains(&Effect::AmbientOcclusion) {
+            self.ssao.compute(&self.scene, projection)
+        } else {
+            0
+        };
+        if self.settings.show_normals {
+            self.pass(&self.normals_shader, self.scene.colors[1], None);
+        } else if effects.is_empty() {
+            // Nothing to do but copy the scene over. Not by blitting, which can't be done into a
+            // multisampled window.
+            self.pass(&self.copy_shader, self.scene.colors[0], None);
+        } else {
+            let mut source = self.scene.colors[0];
+            for (i, effect) in effects.iter().enumerate() {
+                let target = if i + 1 == effects.len() { None } else { Some(&self.ping_pong[i % 2]) };
+                let s = &self.settings;
+                let shader = match effect {
+                    Effect::AmbientOcclusion => {
+                        self.ao_shader.activate();
+                        gl::Uniform1f(self.ao_shader.get_uniform_location("strength"), self.ssao.settings.strength);
+                        for (unit, (name, texture)) in [("ambient", self.scene.colors[2]), ("occlusion", occlusion)].iter().enumerate() {
+                            gl::Uniform1i(self.ao_shader.get_uniform_location(name), unit as i32 + 1);
+                            gl::ActiveTexture(gl::TEXTURE1 + unit as u32);
+                            gl::BindTexture(gl::TEXTURE_2D, *texture);
+                        }
+                        &self.ao_shader
+                    }
+                    Effect::Bloom => {
+                        let glow = self.blur_bright_parts(source);
+                        self.bloom_shader.activate();
+                        gl::Uniform1f(self.bloom_shader.get_uniform_location("strength"), s.bloom_strength);
+                        gl::Uniform1i(self.bloom_shader.get_uniform_location("bloom"), 1);
+                        gl::ActiveTexture(gl::TEXTURE1);
+                        gl::BindTexture(gl::TEXTURE_2D, glow);
+                        &self.bloom_shader
+                    }
+                    Effect::ToneMapping => {
+                        self.tone_shader.activate();
+                        gl::Uniform1f(self.tone_shader.get_uniform_location("exposure"), s.exposure);
+                        &self.tone_shader
+                    }
+                    Effect::Vignette => {
+                        self.vignette_shader.activate();
+                        gl::Uniform1f(self.vignette_shader.get_uniform_location("strength"), s.vignette_strength);
+                        &self.vignette_shader
+                    }
+                    Effect::Gamma => {
+                        self.gamma_shader.activate();
+                        gl::Uniform1f(self.gamma_shader.get_uniform_location("gamma"), s.gamma);
+                        &self.gamma_shader
+                    }
+                    Effect::Fxaa => &self.fxaa_shader,
+                    Effect::Smaa => {
+                        let weights = self.smaa_weights_of(source);
+                        self.smaa_blend_shader.activate();
+                        gl::Uniform1i(self.smaa_blend_shader.get_uniform_location("weights"), 1);
+                        gl::ActiveTexture(gl::TEXTURE1);
+                        gl::BindTexture(gl::TEXTURE_2D, weights);
+                        &self.smaa_blend_shader
+                    }
+                };
+                self.pass(shader, source, target);
+                if let Some(target) = target {
+                    source = target.colors[0];
+                }
+            }
+        }
+
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+    }
+
+    // Keeps what is brighter than the threshold, at half resolution, and blurs it. Returns the
+    // texture holding the result.
+    unsafe fn blur_bright_parts(&self, source: u32) -> u32 {
+        self.bright_shader.activate();
+        gl::Uniform1f(self.bright_shader.get_uniform_location("threshold"), self.settings.bloom_threshold);
+        self.pass(&self.bright_shader, source, Some(&self.bloom[0]));
+
+        self.blur_shader.activate();
+        let direction = self.blur_shader.get_uniform_location("direction");
+        for _ in 0..self.settings.bloom_blur_passes {
+            gl::Uniform2f(direction, 1.0, 0.0);
+            self.pass(&self.blur_shader, self.bloom[0].colors[0], Some(&self.bloom[1]));
+            gl::Uniform2f(direction, 0.0, 1.0);
+            self.pass(&self.blur_shader, self.bloom[1].colors[0], Some(&self.bloom[0]));
+        }
+        self.bloom[0].colors[0]
+    }
+
+    // The first two passes of SMAA: finds the edges in the image, then how much each pixel should
+    // be blended with its neighbours across them. Returns the texture holding the weights.
+    unsafe fn smaa_weights_of(&self, source: u32) -> u32 {
+        self.pass(&self.smaa_edge_shader, source, Some(&self.smaa_edges));
+        self.pass(&self.smaa_weight_shader, self.smaa_edges.colors[0], Some(&self.smaa_weights));
+        self.smaa_weights.colors[0]
+    }
+
+    // Draws a full-screen triangle with the shader, reading `source` and writing to the target,
+    // or to the window if there is none. `texel_size` is the size of a pixel of the target.
+    unsafe fn pass(&self, shader: &Shader, source: u32, target: Option<&Framebuffer>) {
+        let (width, height) = match target {
+            Some(target) => {
+                target.bind();
+                (target.width, target.height)
+            }
+            None => {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+                gl::Viewport(0, 0, self.width as i32, self.height as i32);
+                (self.width, self.height)
+            }
+        };
+
+        shader.activate();
+        gl::Uniform1i(shader.get_uniform_location("source"), 0);
+        gl::Uniform2f(shader.get_uniform_location("texel_size"), 1.0 / width as f32, 1.0 / height as f32);
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(gl::TEXTURE_2D, source);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+    }
+}
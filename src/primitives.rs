@@ -0,0 +1,268 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use crate::mesh::Mesh;
+
+// Generators for simple shapes, handy for prototyping.
+//
+// Every shape is centered on the origin with +Y up, has per-vertex normals and texture
+// coordinates, and winds its triangles counter-clockwise seen from the outside, so nothing is
+// lost to back-face culling. Shapes made by revolving a profile around the Y axis share the
+// vertices along their seam, twice, so the texture coordinates can wrap around.
+
+// Collects vertices and triangles before turning them into a `Mesh`
+struct Builder {
+    vertices : Vec<f32>,
+    normals  : Vec<f32>,
+    uvs      : Vec<f32>,
+    indices  : Vec<u32>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Builder { vertices: vec![], normals: vec![], uvs: vec![], indices: vec![] }
+    }
+
+    fn vertex(&mut self, position: glm::Vec3, normal: glm::Vec3, uv: glm::Vec2) -> u32 {
+        self.vertices.extend(&[position.x, position.y, position.z]);
+        self.normals.extend(&[normal.x, normal.y, normal.z]);
+        self.uvs.extend(&[uv.x, uv.y]);
+        (self.vertices.len() / 3 - 1) as u32
+    }
+
+    fn triangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend(&[a, b, c]);
+    }
+
+    // A flat grid starting at `corner`, spanning `u` and `v`. The triangles face `u` x `v`.
+    fn grid(&mut self, corner: glm::Vec3, u: glm::Vec3, v: glm::Vec3, divisions_u: u32, divisions_v: u32) {
+        let normal = glm::normalize(&glm::cross(&u, &v));
+        let first = self.vertices.len() as u32 / 3;
+        for j in 0..=divisions_v {
+            for i in 0..=divisions_u {
+                let (s, t) = (i as f32 / divisions_u as f32, j as f32 / divisions_v as f32);
+                self.vertex(corner + u * s + v * t, normal, glm::vec2(s, t));
+            }
+        }
+        let row = divisions_u + 1;
+        for j in 0..divisions_v {
+            for i in 0..divisions_u {
+                let a = first + j * row + i;
+                let (b, c, d) = (a + 1, a + row + 1, a + row);
+                self.triangle(a, b, c);
+                self.triangle(a, c, d);
+            }
+        }
+    }
+
+    // Revolves the profile, given from top to bottom, around the Y axis
+    fn lathe(&mut self, profile: &[ProfilePoint], segments: u32) {
+        let first = self.vertices.len() as u32 / 3;
+        for point in profile {
+            for s in 0..=segments {
+                let phi = 2.0 * PI * s as f32 / segments as f32;
+                let (sin, cos) = phi.sin_cos();
+                self.vertex(
+                    glm::vec3(point.radius * sin, point.y, point.radius * cos),
+                    glm::vec3(point.normal.x * sin, point.normal.y, point.normal.x * cos),
+                    glm::vec2(s as f32 / segments as f32, point.v),
+                );
+            }
+        }
+        let row = segments + 1;
+        for k in 0..profile.len() as u32 - 1 {
+            for s in 0..segments {
+                let a = first + k * row + s;
+                let (b, c, d) = (a + 1, a + row + 1, a + row);
+                // Skip the triangles that collapse where the profile touches the axis
+                if profile[k as usize + 1].radius > 0.0 { self.triangle(a, d, c) }
+                if profile[k as usize].radius > 0.0 { self.triangle(a, c, b) }
+            }
+        }
+    }
+
+    // A flat disc at height `y`, facing up or down
+    fn disc(&mut self, radius: f32, y: f32, segments: u32, up: bool) {
+        let normal = glm::vec3(0.0, if up { 1.0 } else { -1.0 }, 0.0);
+        let center = self.vertex(glm::vec3(0.0, y, 0.0), normal, glm::vec2(0.5, 0.5));
+        for s in 0..=segments {
+            let (sin, cos) = (2.0 * PI * s as f32 / segments as f32).sin_cos();
+            self.vertex(glm::vec3(radius * sin, y, radius * cos), normal, glm::vec2(0.5 + 0.5 * sin, 0.5 + 0.5 * cos));
+        }
+        for s in 0..segments {
+            let (a, b) = (center + 1 + s, center + 2 + s);
+            if up { self.triangle(center, a, b) } else { self.triangle(center, b, a) }
+        }
+    }
+
+    fn build(self, color: [f32; 4]) -> Mesh {
+        let colors = color.iter().cloned().cycle().take(self.vertices.len() / 3 * 4).collect();
+        let mut mesh = Mesh::new(self.vertices, self.normals, colors, self.indices);
+        mesh.uvs = self.uvs;
+        mesh
+    }
+}
+
+struct ProfilePoint {
+    radius : f32,
+    y      : f32,
+    normal : glm::Vec2, // Outwards from the axis (x) and up (y)
+    v      : f32,       // Texture coordinate along the profile
+}
+
+// A box with the given side lengths, each face split into `divisions` by `divisions` squares
+pub fn cuboid(size: glm::Vec3, divisions: u32, color: [f32; 4]) -> Mesh {
+    let h = size * 0.5;
+    let (x, y, z) = (glm::vec3(size.x, 0.0, 0.0), glm::vec3(0.0, size.y, 0.0), glm::vec3(0.0, 0.0, size.z));
+    let mut builder = Builder::new();
+    builder.grid(glm::vec3( h.x, -h.y,  h.z), -z,  y, divisions, divisions); // +X
+    builder.grid(glm::vec3(-h.x, -h.y, -h.z),  z,  y, divisions, divisions); // -X
+    builder.grid(glm::vec3(-h.x,  h.y,  h.z),  x, -z, divisions, divisions); // +Y
+    builder.grid(glm::vec3(-h.x, -h.y, -h.z),  x,  z, divisions, divisions); // -Y
+    builder.grid(glm::vec3(-h.x, -h.y,  h.z),  x,  y, divisions, divisions); // +Z
+    builder.grid(glm::vec3( h.x, -h.y, -h.z), -x,  y, divisions, divisions); // -Z
+    builder.build(color)
+}
+
+// A flat grid in the XZ plane facing up
+pub fn plane(width: f32, depth: f32, divisions_x: u32, divisions_z: u32, color: [f32; 4]) -> Mesh {
+    let mut builder = Builder::new();
+    builder.grid(
+        glm::vec3(-width / 2.0, 0.0, depth / 2.0),
+        glm::vec3(width, 0.0, 0.0),
+        glm::vec3(0.0, 0.0, -depth),
+        divisions_x, divisions_z,
+    );
+    builder.build(color)
+}
+
+// A sphere of `rings` bands of latitude and `segments` of longitude
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32, color: [f32; 4]) -> Mesh {
+    let profile: Vec<ProfilePoint> = (0..=rings).map(|r| {
+        let theta = PI * r as f32 / rings as f32;
+        let (sin, cos) = theta.sin_cos();
+        // Exactly on the axis at the poles, where sin(pi) isn't quite zero
+        let ring_radius = if r == 0 || r == rings { 0.0 } else { radius * sin };
+        ProfilePoint { radius: ring_radius, y: radius * cos, normal: glm::vec2(sin, cos), v: 1.0 - r as f32 / rings as f32 }
+    }).collect();
+    let mut builder = Builder::new();
+    builder.lathe(&profile, segments);
+    builder.build(color)
+}
+
+// A sphere made by splitting the faces of an icosahedron `subdivisions` times, giving evenly
+// sized triangles. The texture coordinates are spherical, and stretch along the seam.
+pub fn icosphere(radius: f32, subdivisions: u32, color: [f32; 4]) -> Mesh {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut points: Vec<glm::Vec3> = [
+        (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+        (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+        (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+    ].iter().map(|&(x, y, z)| glm::normalize(&glm::vec3(x, y, z))).collect();
+    let mut faces: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Each edge is split once, however many faces share it
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, points: &mut Vec<glm::Vec3>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push(glm::normalize(&(points[a as usize] + points[b as usize])));
+                points.len() as u32 - 1
+            })
+        };
+        faces = faces.iter().flat_map(|&[a, b, c]| {
+            let ab = midpoint(a, b, &mut points);
+            let bc = midpoint(b, c, &mut points);
+            let ca = midpoint(c, a, &mut points);
+            vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+        }).collect();
+    }
+
+    let mut builder = Builder::new();
+    for p in &points {
+        let uv = glm::vec2(0.5 + p.x.atan2(p.z) / (2.0 * PI), 0.5 + p.y.asin() / PI);
+        builder.vertex(p * radius, *p, uv);
+    }
+    for [a, b, c] in faces {
+        builder.triangle(a, b, c);
+    }
+    builder.build(color)
+}
+
+// A closed cylinder around the Y axis
+pub fn cylinder(radius: f32, height: f32, segments: u32, color: [f32; 4]) -> Mesh {
+    let h = height / 2.0;
+    let mut builder = Builder::new();
+    builder.lathe(&[
+        ProfilePoint { radius, y:  h, normal: glm::vec2(1.0, 0.0), v: 1.0 },
+        ProfilePoint { radius, y: -h, normal: glm::vec2(1.0, 0.0), v: 0.0 },
+    ], segments);
+    builder.disc(radius, h, segments, true);
+    builder.disc(radius, -h, segments, false);
+    builder.build(color)
+}
+
+// A cone around the Y axis with its tip at the top
+pub fn cone(radius: f32, height: f32, segments: u32, color: [f32; 4]) -> Mesh {
+    let h = height / 2.0;
+    let normal = glm::normalize(&glm::vec2(height, radius));
+    let mut builder = Builder::new();
+    builder.lathe(&[
+        ProfilePoint { radius: 0.0, y:  h, normal, v: 1.0 },
+        ProfilePoint { radius,      y: -h, normal, v: 0.0 },
+    ], segments);
+    builder.disc(radius, -h, segments, false);
+    builder.build(color)
+}
+
+// A ring around the Y axis. `major_radius` is the distance to the middle of the tube, and
+// `minor_radius` the radius of the tube itself.
+pub fn torus(major_radius: f32, minor_radius: f32, segments: u32, tube_segments: u32, color: [f32; 4]) -> Mesh {
+    // Going around the tube outside first, then down, in and up again
+    let profile: Vec<ProfilePoint> = (0..=tube_segments).map(|i| {
+        let v = i as f32 / tube_segments as f32;
+        let (sin, cos) = (2.0 * PI * v).sin_cos();
+        ProfilePoint {
+            radius: major_radius + minor_radius * cos,
+            y: -minor_radius * sin,
+            normal: glm::vec2(cos, -sin),
+            v,
+        }
+    }).collect();
+    let mut builder = Builder::new();
+    builder.lathe(&profile, segments);
+    builder.build(color)
+}
+
+// A cylinder of the given `height` with half spheres on both ends, each made of `rings` bands
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32, color: [f32; 4]) -> Mesh {
+    let h = height / 2.0;
+    let total = height + PI * radius; // Length of the profile, for spreading out the texture
+    let mut profile = vec![];
+    for r in 0..=rings {
+        let theta = PI / 2.0 * r as f32 / rings as f32;
+        let (sin, cos) = theta.sin_cos();
+        let along = radius * theta;
+        profile.push(ProfilePoint { radius: radius * sin, y: h + radius * cos, normal: glm::vec2(sin, cos), v: 1.0 - along / total });
+    }
+    for r in 0..=rings {
+        let theta = PI / 2.0 * (1.0 + r as f32 / rings as f32);
+        let (sin, cos) = theta.sin_cos();
+        let along = radius * theta + height;
+        profile.push(ProfilePoint { radius: radius * sin, y: -h + radius * cos, normal: glm::vec2(sin, cos), v: 1.0 - along / total });
+    }
+    // Exactly on the axis at the poles, where sin(pi) isn't quite zero
+    profile.first_mut().unwrap().radius = 0.0;
+    profile.last_mut().unwrap().radius = 0.0;
+
+    let mut builder = Builder::new();
+    builder.lathe(&profile, segments);
+    builder.build(color)
+}
@@ -0,0 +1,88 @@
+use serde::{Serialize, Deserialize};
+
+use crate::post::AntiAliasing;
+
+// How the scene is rendered, read from a RON file and then overridden by the command line:
+//
+//     RenderConfig(
+//         window_samples: 0,
+//         framebuffer_samples: 4,
+//         anti_aliasing: Fxaa,
+//     )
+//
+// Anything left out of the file keeps its default. Sample counts must be 0 or a power of two.
+
+pub const DEFAULT_PATH: &str = "./resources/render.ron";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename = "RenderConfig", default)]
+pub struct RenderConfig {
+    pub window_samples      : u16,          // MSAA samples of the window itself, 0 for none
+    pub framebuffer_samples : u32,          // MSAA samples the scene is drawn with, 0 or 1 for none
+    pub anti_aliasing       : AntiAliasing, // Done in post-processing, on top of any MSAA
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            window_samples: 0,
+            framebuffer_samples: 4,
+            anti_aliasing: AntiAliasing::Fxaa,
+        }
+    }
+}
+
+impl RenderConfig {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read render config {}: {}", path, e))?;
+        ron::from_str(&text)
+            .map_err(|e| format!("Failed to parse render config {}: {}", path, e))
+    }
+
+    // The file given as `--config <path>`, or the default one if it exists, with
+    // `--window-msaa <samples>`, `--msaa <samples>` and `--aa <none|fxaa|smaa>` on top
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let value_of = |flag: &str| args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1));
+
+        let mut config = match value_of("--config") {
+            Some(path) => RenderConfig::load(path)?,
+            None if std::path::Path::new(DEFAULT_PATH).exists() => RenderConfig::load(DEFAULT_PATH)?,
+            None => RenderConfig::default(),
+        };
+        if let Some(samples) = value_of("--window-msaa") {
+            config.window_samples = samples.parse().map_err(|_| format!("Bad sample count {}", samples))?;
+        }
+        if let Some(samples) = value_of("--msaa") {
+            config.framebuffer_samples = samples.parse().map_err(|_| format!("Bad sample count {}", samples))?;
+        }
+        if let Some(method) = value_of("--aa") {
+            config.anti_aliasing = match method.to_lowercase().as_str() {
+                "none" => AntiAliasing::None,
+                "fxaa" => AntiAliasing::Fxaa,
+                "smaa" => AntiAliasing::Smaa,
+                _ => return Err(format!("Unknown anti-aliasing {}, expected none, fxaa or smaa", method)),
+            };
+        }
+
+        // Anything else makes glutin panic, or the framebuffers incomplete
+        for (what, samples) in [("window", config.window_samples as u32), ("framebuffer", config.framebuffer_samples)] {
+            if samples != 0 && !samples.is_power_of_two() {
+                return Err(format!("The {} sample count must be 0 or a power of two, not {}", what, samples));
+            }
+        }
+        Ok(config)
+    }
+
+    // The framebuffer samples, cut down to as many as the driver supports. Needs a current
+    // OpenGL context.
+    pub unsafe fn supported_framebuffer_samples(&self) -> u32 {
+        let mut max_samples = 0;
+        gl::GetIntegerv(gl::MAX_SAMPLES, &mut max_samples);
+        let max_samples = max_samples.max(0) as u32;
+        if self.framebuffer_samples > max_samples {
+            println!("Drawing with {} samples, as {} aren't supported", max_samples, self.framebuffer_samples);
+        }
+        self.framebuffer_samples.min(max_samples)
+    }
+}
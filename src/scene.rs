@@ -0,0 +1,235 @@
+extern crate nalgebra_glm as glm;
+
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::animation::{Animation, Interpolation, LoopMode, Property, Track};
+use crate::mesh::{self, Mesh};
+use crate::scene_graph::{self, Node, SceneNode};
+
+// Scene description files.
+//
+// A scene file describes the meshes to load and a tree of nodes using them, written in RON:
+//
+//     Scene(
+//         meshes: {
+//             "body": (path: "./resources/helicopter.obj", object: Some("Body_body"), color: (0.3, 0.3, 0.3, 1.0)),
+//         },
+//         root: (
+//             name: "helicopter",
+//             mesh: Some("body"),
+//             position: (0.0, 10.0, 0.0),
+//             rotation: Euler(0.0, 3.14, 0.0),
+//             children: [ ... ],
+//         ),
+//     )
+//
+// Everything but a node's name may be left out. Animations are attached to the node they move.
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename = "Scene")]
+pub struct SceneDescription {
+    #[serde(default)]
+    pub meshes : HashMap<String, MeshDescription>,
+    pub root   : NodeDescription,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MeshDescription {
+    pub path   : String,
+    #[serde(default)]
+    pub object : Option<String>, // Which object in the OBJ file, may be left out if there's only one
+    #[serde(default = "white")]
+    pub color  : [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum RotationDescription {
+    Euler(f32, f32, f32),           // Radians around X, Y and Z, in the default order of `SceneNode`
+    Quaternion(f32, f32, f32, f32), // [i, j, k, w]
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeDescription {
+    pub name            : String,
+    #[serde(default)]
+    pub mesh            : Option<String>, // Key into the meshes of the scene
+    #[serde(default)]
+    pub position        : [f32; 3],
+    #[serde(default = "no_rotation")]
+    pub rotation        : RotationDescription,
+    #[serde(default = "unit_scale")]
+    pub scale           : [f32; 3],
+    #[serde(default)]
+    pub reference_point : [f32; 3],
+    #[serde(default = "white")]
+    pub tint            : [f32; 4],       // What the colours of the mesh are multiplied with
+    #[serde(default)]
+    pub animations      : Vec<AnimationDescription>,
+    #[serde(default)]
+    pub children        : Vec<NodeDescription>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnimationDescription {
+    pub name      : String,
+    pub loop_mode : LoopMode,
+    pub tracks    : Vec<TrackDescription>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrackDescription {
+    pub property      : Property,
+    pub interpolation : Interpolation,
+    pub keyframes     : Vec<(f32, [f32; 3])>, // (time, value)
+}
+
+fn white() -> [f32; 4] { [1.0, 1.0, 1.0, 1.0] }
+fn no_rotation() -> RotationDescription { RotationDescription::Euler(0.0, 0.0, 0.0) }
+fn unit_scale() -> [f32; 3] { [1.0, 1.0, 1.0] }
+
+impl SceneDescription {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read scene {}: {}", path, e))?;
+        ron::from_str(&text)
+            .map_err(|e| format!("Failed to parse scene {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| format!("Failed to serialize scene: {}", e))?;
+        std::fs::write(path, text)
+            .map_err(|e| format!("Failed to write scene {}: {}", path, e))
+    }
+
+    // Loads the meshes and builds the scene graph. `create_vao` uploads a mesh to the GPU,
+    // returning its VAO id.
+    pub fn instantiate(&self, create_vao: &dyn Fn(&Mesh) -> u32) -> Result<LoadedScene, String> {
+        // Each OBJ file is only parsed once, however many of its objects we use
+        let mut files = HashMap::<&str, Vec<(String, Mesh)>>::new();
+        let mut meshes = HashMap::new();
+        for (key, description) in &self.meshes {
+            if !files.contains_key(description.path.as_str()) {
+                files.insert(&description.path, mesh::load_obj_meshes(&description.path)?);
+            }
+            let models = &files[description.path.as_str()];
+            let (_, found) = match &description.object {
+                Some(object) => models.iter().find(|(name, _)| name == object)
+                    .ok_or(format!("No object named {} in {}", object, description.path))?,
+                None if models.len() == 1 => &models[0],
+                None => return Err(format!("{} has {} objects, please name one for mesh {}", description.path, models.len(), key)),
+            };
+            let mut mesh = found.clone();
+            mesh.set_color(description.color);
+            meshes.insert(key.clone(), mesh);
+        }
+
+        let vaos: HashMap<String, u32> = meshes.iter()
+            .map(|(key, mesh)| (key.clone(), create_vao(mesh)))
+            .collect();
+
+        // The meshes are never moved after this, so the nodes can safely point into the map
+        let mut animations = vec![];
+        let root = build_node(&self.root, &meshes, &vaos, &mut animations)?;
+        Ok(LoadedScene {
+            root,
+            meshes,
+            vaos,
+            animations,
+            description: self.clone(),
+        })
+    }
+}
+
+fn build_node(description: &NodeDescription, meshes: &HashMap<String, Mesh>, vaos: &HashMap<String, u32>, animations: &mut Vec<Animation>) -> Result<Node, String> {
+    let mut node = match &description.mesh {
+        Some(key) => {
+            let mesh = meshes.get(key).ok_or(format!("Node {} uses unknown mesh {}", description.name, key))?;
+            let mut node = SceneNode::from_vao(vaos[key], mesh.index_count);
+            node.attach_mesh(mesh);
+            node
+        }
+        None => SceneNode::new(),
+    };
+    node.name = description.name.clone();
+    node.position = glm::make_vec3(&description.position);
+    node.rotation = match description.rotation {
+        RotationDescription::Euler(x, y, z) => scene_graph::quat_from_euler(&glm::vec3(x, y, z), scene_graph::DEFAULT_EULER_ORDER),
+        RotationDescription::Quaternion(i, j, k, w) => glm::quat(i, j, k, w),
+    };
+    node.scale = glm::make_vec3(&description.scale);
+    node.reference_point = glm::make_vec3(&description.reference_point);
+    node.tint = glm::make_vec4(&description.tint);
+
+    for animation in &description.animations {
+        let mut built = Animation::new(&animation.name, animation.loop_mode);
+        for track in &animation.tracks {
+            let mut built_track = Track::new(&node, track.property, track.interpolation);
+            for (time, value) in &track.keyframes {
+                built_track.add_keyframe(*time, glm::make_vec3(value));
+            }
+            built.tracks.push(built_track);
+        }
+        animations.push(built);
+    }
+
+    for child in &description.children {
+        let child = build_node(child, meshes, vaos, animations)?;
+        node.add_child(&child);
+    }
+    Ok(node)
+}
+
+pub struct LoadedScene {
+    pub root        : Node,
+    pub meshes      : HashMap<String, Mesh>,
+    pub vaos        : HashMap<String, u32>,
+    pub animations  : Vec<Animation>,    // Ready to be handed to an `AnimationPlayer`
+    pub description : SceneDescription,  // What the scene was loaded from
+}
+
+impl LoadedScene {
+    // Describes the graph as it is now, e.g. after animating or moving things around, with the
+    // meshes and animations it was loaded with
+    pub fn snapshot(&self) -> SceneDescription {
+        let mesh_names: HashMap<*const Mesh, String> = self.meshes.iter()
+            .map(|(key, mesh)| (mesh as *const Mesh, key.clone()))
+            .collect();
+
+        let mut animations = HashMap::new();
+        collect_animations(&self.description.root, &mut animations);
+
+        SceneDescription {
+            meshes: self.description.meshes.clone(),
+            root: describe_node(&self.root, &mesh_names, &animations),
+        }
+    }
+}
+
+fn collect_animations<'a>(description: &'a NodeDescription, animations: &mut HashMap<&'a str, &'a Vec<AnimationDescription>>) {
+    animations.insert(&description.name, &description.animations);
+    for child in &description.children {
+        collect_animations(child, animations);
+    }
+}
+
+// Describes the node and everything below it. Meshes are looked up by the address the nodes
+// point to, and nodes using meshes not in `mesh_names` are written without one.
+pub fn describe_node(node: &SceneNode, mesh_names: &HashMap<*const Mesh, String>, animations: &HashMap<&str, &Vec<AnimationDescription>>) -> NodeDescription {
+    let q = node.rotation;
+    NodeDescription {
+        name: node.name.clone(),
+        mesh: mesh_names.get(&node.mesh).cloned(),
+        position: [node.position.x, node.position.y, node.position.z],
+        rotation: RotationDescription::Quaternion(q.i, q.j, q.k, q.w),
+        scale: [node.scale.x, node.scale.y, node.scale.z],
+        reference_point: [node.reference_point.x, node.reference_point.y, node.reference_point.z],
+        tint: [node.tint.x, node.tint.y, node.tint.z, node.tint.w],
+        animations: animations.get(node.name.as_str()).map_or(vec![], |a| (*a).clone()),
+        children: node.children.iter()
+            .map(|&child| describe_node(unsafe { &*child }, mesh_names, animations))
+            .collect(),
+    }
+}
@@ -3,6 +3,10 @@ extern crate nalgebra_glm as glm;
 use std::mem::ManuallyDrop;
 use std::pin::Pin;
 
+use crate::bounds::Bounds;
+use crate::lod::LodLevel;
+use crate::mesh::Mesh;
+
 // Used to create an unholy abomination upon which you should not cast your gaze. This ended up
 // being a necessity due to wanting to keep the code written by students as "straight forward" as
 // possible. It is very very double plus ungood Rust, and intentionally leaks memory like a sieve.
@@ -16,14 +20,62 @@ use std::pin::Pin;
 // having what I arbitrarily decided to be the required level of "simplicity of use".
 pub type Node = ManuallyDrop<Pin<Box<SceneNode>>>;
 
+// The order in which Euler angles are combined, read as a matrix product. `Yxz` means
+// `Ry * Rx * Rz`, i.e. the rotation around Z is applied first and the one around Y last.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EulerOrder {
+    Xyz,
+    Xzy,
+    Yxz,
+    Yzx,
+    Zxy,
+    Zyx,
+}
+
+// The order the plain Euler angle helpers use, matching how the rotation vector used to be drawn
+pub const DEFAULT_EULER_ORDER: EulerOrder = EulerOrder::Yxz;
+
+// Angles are around the X, the Y and the Z axes, measured in radians
+pub fn quat_from_euler(angles: &glm::Vec3, order: EulerOrder) -> glm::Quat {
+    let x = glm::quat_angle_axis(angles.x, &glm::vec3(1.0, 0.0, 0.0));
+    let y = glm::quat_angle_axis(angles.y, &glm::vec3(0.0, 1.0, 0.0));
+    let z = glm::quat_angle_axis(angles.z, &glm::vec3(0.0, 0.0, 1.0));
+    match order {
+        EulerOrder::Xyz => x * y * z,
+        EulerOrder::Xzy => x * z * y,
+        EulerOrder::Yxz => y * x * z,
+        EulerOrder::Yzx => y * z * x,
+        EulerOrder::Zxy => z * x * y,
+        EulerOrder::Zyx => z * y * x,
+    }
+}
+
+// An orientation where the local -Z axis points along `direction`, the OpenGL forward
+pub fn quat_looking_along(direction: &glm::Vec3, up: &glm::Vec3) -> glm::Quat {
+    // `quat_look_at` gives the rotation of a view matrix, which is the inverse of what we want
+    glm::quat_conjugate(&glm::quat_look_at(&glm::normalize(direction), up))
+}
+
 pub struct SceneNode {
+    pub name            : String,      // What I'm called, for finding me again
+
     pub position        : glm::Vec3,   // Where I should be in relation to my parent
-    pub rotation        : glm::Vec3,   // How I should be rotated, around the X, the Y and the Z axes
+    pub rotation        : glm::Quat,   // How I should be rotated
     pub scale           : glm::Vec3,   // How I should be scaled
     pub reference_point : glm::Vec3,   // The point I shall rotate and scale about
 
     pub vao_id      : u32,             // What I should draw
     pub index_count : i32,             // How much of it there is to draw
+    pub tint        : glm::Vec4,       // What my colours are multiplied with
+    pub texture_id  : u32,             // Base colour texture my colours are multiplied with, 0 for none
+    pub translucent : bool,            // Whether any of my colours are see-through
+
+    pub bounds       : Option<Bounds>, // What I cover, before being transformed
+    pub world_bounds : Option<Bounds>, // What I cover in the world, as of the last time I was drawn
+    pub lods         : Vec<LodLevel>,  // Simpler versions of what I draw, finest first
+    pub mesh         : *const Mesh,    // CPU-side copy of what I draw, if any, for picking
+
+    pub world_transform : glm::Mat4,   // Where I was in the world the last time I was drawn
 
     pub children: Vec<*mut SceneNode>, // Those I command
 }
@@ -32,28 +84,109 @@ impl SceneNode {
 
     pub fn new() -> Node {
         ManuallyDrop::new(Pin::new(Box::new(SceneNode {
+            name            : String::new(),
             position        : glm::zero(),
-            rotation        : glm::zero(),
+            rotation        : glm::quat_identity(),
             scale           : glm::vec3(1.0, 1.0, 1.0),
             reference_point : glm::zero(),
             vao_id          : 0,
             index_count     : -1,
+            tint            : glm::vec4(1.0, 1.0, 1.0, 1.0),
+            texture_id      : 0,
+            translucent     : false,
+            bounds          : None,
+            world_bounds    : None,
+            lods            : vec![],
+            mesh            : std::ptr::null(),
+            world_transform : glm::identity(),
             children        : vec![],
         })))
     }
 
     pub fn from_vao(vao_id: u32, index_count: i32) -> Node {
         ManuallyDrop::new(Pin::new(Box::new(SceneNode {
+            name            : String::new(),
             position        : glm::zero(),
-            rotation        : glm::zero(),
+            rotation        : glm::quat_identity(),
             scale           : glm::vec3(1.0, 1.0, 1.0),
             reference_point : glm::zero(),
             vao_id,
             index_count,
+            tint: glm::vec4(1.0, 1.0, 1.0, 1.0),
+            texture_id: 0,
+            translucent: false,
+            bounds: None,
+            world_bounds: None,
+            lods: vec![],
+            mesh: std::ptr::null(),
+            world_transform: glm::identity(),
             children: vec![],
         })))
     }
 
+    // Convenience for setting the rotation from angles around the X, the Y and the Z axes
+    pub fn set_euler_angles(&mut self, angles: glm::Vec3) {
+        self.rotation = quat_from_euler(&angles, DEFAULT_EULER_ORDER);
+    }
+
+    pub fn set_euler_angles_ordered(&mut self, angles: glm::Vec3, order: EulerOrder) {
+        self.rotation = quat_from_euler(&angles, order);
+    }
+
+    pub fn set_axis_angle(&mut self, axis: &glm::Vec3, angle: f32) {
+        self.rotation = glm::quat_angle_axis(angle, &glm::normalize(axis));
+    }
+
+    // Turns me so that my -Z axis faces `target`. Both are given in my parent's space.
+    pub fn look_at(&mut self, target: &glm::Vec3, up: &glm::Vec3) {
+        let direction = target - self.position;
+        if glm::length(&direction) > 1e-6 {
+            self.rotation = quat_looking_along(&direction, up);
+        }
+    }
+
+    // Rotates me towards `target` along the shortest arc, `t` = 0 keeps me as I am
+    pub fn slerp_to(&mut self, target: &glm::Quat, t: f32) {
+        self.rotation = glm::quat_slerp(&self.rotation, target, t);
+    }
+
+    pub fn rotation_matrix(&self) -> glm::Mat4 {
+        glm::quat_to_mat4(&self.rotation)
+    }
+
+    // Remembers my transformation to world space, and moves my world space bounds along
+    pub fn update_world_transform(&mut self, world_transformation: &glm::Mat4) {
+        self.world_transform = *world_transformation;
+        self.world_bounds = self.bounds.map(|b| b.transformed(world_transformation));
+    }
+
+    // The mesh must outlive me, just like my children
+    pub fn attach_mesh(&mut self, mesh: &Mesh) {
+        self.mesh = mesh as *const Mesh;
+        self.bounds = Some(mesh.bounds);
+        self.translucent = mesh.colors.chunks(4).any(|c| c.len() == 4 && c[3] < 1.0);
+    }
+
+    // Has to be drawn after everything opaque, blended with what is behind it
+    pub fn is_transparent(&self) -> bool {
+        self.translucent || self.tint.w < 1.0
+    }
+
+    pub fn mesh(&self) -> Option<&Mesh> {
+        unsafe { self.mesh.as_ref() }
+    }
+
+    // Searches me and everything below me for a node with the given name
+    pub fn find(&mut self, name: &str) -> Option<&mut SceneNode> {
+        if self.name == name { return Some(self) }
+        for &child in &self.children {
+            if let Some(node) = unsafe { (*child).find(name) } {
+                return Some(node)
+            }
+        }
+        None
+    }
+
     pub fn add_child(&mut self, child: &SceneNode) {
         self.children.push(child as *const SceneNode as *mut SceneNode)
     }
@@ -74,22 +207,25 @@ impl SceneNode {
     pub fn print(&self) {
         println!(
 "SceneNode {{
+    Name:      {}
     VAO:       {}
     Indices:   {}
     Children:  {}
     Position:  [{:.2}, {:.2}, {:.2}]
-    Rotation:  [{:.2}, {:.2}, {:.2}]
+    Rotation:  [{:.2}, {:.2}, {:.2}, {:.2}]
     Reference: [{:.2}, {:.2}, {:.2}]
 }}",
+            self.name,
             self.vao_id,
             self.index_count,
             self.children.len(),
             self.position.x,
             self.position.y,
             self.position.z,
-            self.rotation.x,
-            self.rotation.y,
-            self.rotation.z,
+            self.rotation.i,
+            self.rotation.j,
+            self.rotation.k,
+            self.rotation.w,
             self.reference_point.x,
             self.reference_point.y,
             self.reference_point.z,
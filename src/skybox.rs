@@ -0,0 +1,116 @@
+extern crate nalgebra_glm as glm;
+
+use crate::mesh::Mesh;
+use crate::primitives;
+use crate::shader::{Shader, ShaderBuilder};
+
+// The background, drawn after everything else where nothing has been drawn yet.
+//
+// It is a cube around the camera, turning with it but never moving, and always at the far
+// plane: the vertex shader sets z = w, and the depth test lets it through only where the depth
+// buffer still holds the cleared 1.0. The cube is either textured with a cubemap of six
+// images, or shaded by a procedural starfield with the Earth hanging in it.
+
+// Image names in a cubemap directory, in the order OpenGL wants the faces: +X, -X, +Y, -Y, +Z, -Z
+pub const FACE_NAMES: [&str; 6] = ["right", "left", "top", "bottom", "front", "back"];
+
+pub struct Skybox {
+    pub procedural   : bool,        // Draw the starfield even if there is a cubemap
+    cubemap          : Option<u32>,
+    vao              : u32,
+    index_count      : i32,
+    cubemap_shader   : Shader,
+    starfield_shader : Shader,
+}
+
+impl Skybox {
+    // The cubemap is loaded from the directory if it is given and holds all six faces, and the
+    // starfield is used otherwise. `create_vao` returns the VAO id of a mesh.
+    pub unsafe fn new(directory: Option<&str>, create_vao: &dyn Fn(&Mesh) -> u32) -> Self {
+        let cubemap = directory.and_then(|directory| match load_cubemap(directory) {
+            Ok(cubemap) => Some(cubemap),
+            Err(e) => {
+                println!("{}, using the starfield instead", e);
+                None
+            }
+        });
+        let cube = primitives::cuboid(glm::vec3(2.0, 2.0, 2.0), 1, [1.0; 4]);
+
+        Skybox {
+            procedural: cubemap.is_none(),
+            cubemap,
+            vao: create_vao(&cube),
+            index_count: cube.index_count,
+            cubemap_shader: ShaderBuilder::new()
+                .attach_file("./shaders/skybox.vert")
+                .attach_file("./shaders/skybox.frag")
+                .link(),
+            starfield_shader: ShaderBuilder::new()
+                .attach_file("./shaders/skybox.vert")
+                .attach_file("./shaders/starfield.frag")
+                .link(),
+        }
+    }
+
+    pub fn has_cubemap(&self) -> bool {
+        self.cubemap.is_some()
+    }
+
+    pub fn shaders(&self) -> [&Shader; 2] {
+        [&self.cubemap_shader, &self.starfield_shader]
+    }
+
+    // Draws the background behind what has been drawn so far. The Camera uniform block must be
+    // up to date.
+    pub unsafe fn draw(&self) {
+        let shader = match self.cubemap {
+            Some(cubemap) if !self.procedural => {
+                self.cubemap_shader.activate();
+                gl::Uniform1i(self.cubemap_shader.get_uniform_location("cubemap"), 0);
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+                &self.cubemap_shader
+            }
+            _ => &self.starfield_shader,
+        };
+        shader.activate();
+
+        // The camera is inside the cube, looking at its back faces
+        gl::DepthFunc(gl::LEQUAL);
+        gl::DepthMask(gl::FALSE);
+        gl::Disable(gl::CULL_FACE);
+        gl::BindVertexArray(self.vao);
+        gl::DrawElements(gl::TRIANGLES, self.index_count, gl::UNSIGNED_INT, std::ptr::null());
+        gl::Enable(gl::CULL_FACE);
+        gl::DepthMask(gl::TRUE);
+        gl::DepthFunc(gl::LESS);
+    }
+}
+
+// Loads `right`, `left`, `top`, `bottom`, `front` and `back` from the directory, as PNG or JPEG
+pub unsafe fn load_cubemap(directory: &str) -> Result<u32, String> {
+    let mut faces = vec![];
+    for name in FACE_NAMES.iter() {
+        let path = ["png", "jpg", "jpeg"].iter()
+            .map(|extension| format!("{}/{}.{}", directory, name, extension))
+            .find(|path| std::path::Path::new(path).exists())
+            .ok_or(format!("No {} face for the skybox in {}", name, directory))?;
+        let image = image::open(&path).map_err(|e| format!("Failed to load {}: {}", path, e))?.to_rgba8();
+        faces.push(image);
+    }
+
+    let mut cubemap = 0;
+    gl::GenTextures(1, &mut cubemap);
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+    for (i, face) in faces.iter().enumerate() {
+        gl::TexImage2D(gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32, 0, gl::SRGB8_ALPHA8 as i32,
+            face.width() as i32, face.height() as i32, 0, gl::RGBA, gl::UNSIGNED_BYTE, face.as_ptr() as *const _);
+    }
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as i32);
+    }
+    gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+    Ok(cubemap)
+}
@@ -0,0 +1,141 @@
+extern crate nalgebra_glm as glm;
+
+use rand::Rng;
+
+use crate::framebuffer::Framebuffer;
+use crate::noise;
+use crate::shader::{Shader, ShaderBuilder};
+
+// Screen-space ambient occlusion.
+//
+// For every pixel, points are picked at random in the hemisphere around its normal, and
+// compared against the depth buffer. The more of them that end up behind something, the less
+// ambient light can reach the pixel. The random points are rotated by a small tiling noise
+// texture, and the blur afterwards smooths out the pattern that leaves.
+
+pub const MAX_KERNEL_SIZE: usize = 64;
+const NOISE_SIZE: usize = 4;
+
+#[derive(Clone, Debug)]
+pub struct SsaoSettings {
+    pub enabled     : bool,
+    pub kernel_size : usize, // Samples per pixel, at most MAX_KERNEL_SIZE
+    pub radius      : f32,   // Of the hemisphere, in world units
+    pub bias        : f32,   // Keeps flat surfaces from occluding themselves
+    pub blur_radius : i32,   // In pixels, 0 for no blur
+    pub strength    : f32,   // 0 leaves the ambient light alone, 1 takes all occlusion into account
+}
+
+impl Default for SsaoSettings {
+    // Off until turned on, like the other effects changing how the scene looks
+    fn default() -> Self {
+        SsaoSettings {
+            enabled: false,
+            kernel_size: 32,
+            radius: 1.5,
+            bias: 0.05,
+            blur_radius: 2,
+            strength: 1.0,
+        }
+    }
+}
+
+pub struct Ssao {
+    pub settings  : SsaoSettings,
+    pub shader    : Shader,
+    blur_shader   : Shader,
+    occlusion     : Framebuffer,
+    blurred       : Framebuffer,
+    noise_texture : u32,
+    kernel        : Vec<glm::Vec3>, // Made for the kernel size in the settings
+}
+
+impl Ssao {
+    pub unsafe fn new(width: u32, height: u32) -> Self {
+        let mut rng = noise::seeded_rng(0);
+
+        // Random rotations around the normal, repeated across the screen
+        let rotations: Vec<f32> = (0..NOISE_SIZE * NOISE_SIZE)
+            .flat_map(|_| vec![rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)])
+            .collect();
+        let mut noise_texture = 0;
+        gl::GenTextures(1, &mut noise_texture);
+        gl::BindTexture(gl::TEXTURE_2D, noise_texture);
+        gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RG16F as i32, NOISE_SIZE as i32, NOISE_SIZE as i32, 0, gl::RG, gl::FLOAT, rotations.as_ptr() as *const _);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+
+        Ssao {
+            settings: SsaoSettings::default(),
+            shader: ShaderBuilder::new()
+                .attach_file("./shaders/fullscreen.vert")
+                .attach_file("./shaders/ssao.frag")
+                .link(),
+            blur_shader: ShaderBuilder::new()
+                .attach_file("./shaders/fullscreen.vert")
+                .attach_file("./shaders/ssao_blur.frag")
+                .link(),
+            occlusion: Framebuffer::new(width, height, &[gl::R8], false),
+            blurred: Framebuffer::new(width, height, &[gl::R8], false),
+            noise_texture,
+            kernel: vec![],
+        }
+    }
+
+    pub unsafe fn resize(&mut self, width: u32, height: u32) {
+        self.occlusion.resize(width, height);
+        self.blurred.resize(width, height);
+    }
+
+    // Points in the unit hemisphere around +z, more of them close to the middle
+    fn make_kernel(size: usize) -> Vec<glm::Vec3> {
+        let mut rng = noise::seeded_rng(1);
+        (0..size).map(|i| {
+            let direction = glm::normalize(&glm::vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(0.05..1.0)));
+            let t = i as f32 / size as f32;
+            direction * rng.gen::<f32>() * glm::lerp_scalar(0.1, 1.0, t * t)
+        }).collect()
+    }
+
+    // Works out the occlusion of the scene from its depth and its world space normals (its
+    // second attachment). The Camera uniform block must be up to date. Returns the texture
+    // holding how much ambient light reaches each pixel, from 0 to 1.
+    pub unsafe fn compute(&mut self, scene: &Framebuffer, projection: &glm::Mat4) -> u32 {
+        let kernel_size = self.settings.kernel_size.clamp(1, MAX_KERNEL_SIZE);
+        if self.kernel.len() != kernel_size {
+            self.kernel = Ssao::make_kernel(kernel_size);
+        }
+
+        self.occlusion.bind();
+        self.shader.activate();
+        let s = &self.shader;
+        gl::UniformMatrix4fv(s.get_uniform_location("inverse_projection"), 1, gl::FALSE, glm::value_ptr(&glm::inverse(projection)).as_ptr());
+        gl::Uniform3fv(s.get_uniform_location("samples"), kernel_size as i32, self.kernel.as_ptr() as *const f32);
+        gl::Uniform1i(s.get_uniform_location("kernel_size"), kernel_size as i32);
+        gl::Uniform1f(s.get_uniform_location("radius"), self.settings.radius);
+        gl::Uniform1f(s.get_uniform_location("bias"), self.settings.bias);
+        gl::Uniform2f(s.get_uniform_location("noise_scale"),
+            self.occlusion.width as f32 / NOISE_SIZE as f32, self.occlusion.height as f32 / NOISE_SIZE as f32);
+        for (unit, (name, texture)) in [("depth_buffer", scene.depth), ("normal_buffer", scene.colors[1]), ("noise_texture", self.noise_texture)].iter().enumerate() {
+            gl::Uniform1i(s.get_uniform_location(name), unit as i32);
+            gl::ActiveTexture(gl::TEXTURE0 + unit as u32);
+            gl::BindTexture(gl::TEXTURE_2D, *texture);
+        }
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        if self.settings.blur_radius <= 0 {
+            return self.occlusion.colors[0];
+        }
+        self.blurred.bind();
+        self.blur_shader.activate();
+        gl::Uniform1i(self.blur_shader.get_uniform_location("source"), 0);
+        gl::Uniform1i(self.blur_shader.get_uniform_location("blur_radius"), self.settings.blur_radius);
+        gl::BindTexture(gl::TEXTURE_2D, self.occlusion.colors[0]);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        self.blurred.colors[0]
+    }
+}
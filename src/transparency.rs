@@ -0,0 +1,164 @@
+extern crate nalgebra_glm as glm;
+
+use std::ptr;
+
+use crate::framebuffer::Framebuffer;
+use crate::gltf_import;
+use crate::shader::{Shader, ShaderBuilder};
+
+// Drawing what is see-through, after everything opaque.
+//
+// Transparent nodes are collected while the scene graph is drawn instead of being drawn right
+// away, and drawn at the end without writing depth, so they never hide each other. Either:
+//
+// - sorted back to front by their distance from the camera, and alpha blended in that order,
+//   which is right as long as the nodes don't overlap themselves or each other, or
+// - with weighted blended order-independent transparency (McGuire and Bavoil, 2013), which
+//   needs no sorting: every layer is added up into an accumulation buffer, weighted by how close
+//   and how opaque it is, and the average composited over the scene in the end. An approximation,
+//   but one that doesn't pop as the order changes.
+//
+// Only the colour of the scene is blended into; its normals and ambient light stay those of the
+// opaque surfaces behind.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransparencyMode {
+    Sorted,
+    WeightedBlended,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TransparentDraw {
+    pub vao_id      : u32,
+    pub index_count : i32,
+    pub model       : glm::Mat4,
+    pub tint        : glm::Vec4,
+    pub texture_id  : u32,       // Base colour texture, 0 for none
+    pub center      : glm::Vec3, // In the world, for sorting
+}
+
+pub struct TransparencyPass {
+    pub mode         : TransparencyMode,
+    draws            : Vec<TransparentDraw>,
+    accumulation     : Framebuffer, // Weighted colour sum and revealage, with the scene's depth
+    oit_shader       : Shader,
+    composite_shader : Shader,
+    empty_vao        : u32,
+}
+
+impl TransparencyPass {
+    pub unsafe fn new(width: u32, height: u32) -> Self {
+        let mut empty_vao = 0;
+        gl::GenVertexArrays(1, &mut empty_vao);
+
+        TransparencyPass {
+            mode: TransparencyMode::Sorted,
+            draws: vec![],
+            accumulation: Framebuffer::new(width, height, &[gl::RGBA16F, gl::R16F], true),
+            oit_shader: ShaderBuilder::new()
+                .attach_file("./shaders/simple.vert")
+                .attach_file("./shaders/oit.frag")
+                .attach_file("./shaders/lighting.frag")
+                .link(),
+            composite_shader: ShaderBuilder::new()
+                .attach_file("./shaders/fullscreen.vert")
+                .attach_file("./shaders/oit_composite.frag")
+                .link(),
+            empty_vao,
+        }
+    }
+
+    pub unsafe fn resize(&mut self, width: u32, height: u32) {
+        self.accumulation.resize(width, height);
+    }
+
+    pub fn shaders(&self) -> [&Shader; 1] {
+        [&self.oit_shader]
+    }
+
+    // Queues a node to be drawn in `draw`
+    pub fn add(&mut self, draw: TransparentDraw) {
+        self.draws.push(draw);
+    }
+
+    // Draws and forgets everything queued into the target, which must be bound and hold the
+    // opaque scene. `shader` is the forward shader used for sorted drawing. Returns the number
+    // of draw calls.
+    pub unsafe fn draw(&mut self, shader: &Shader, view: &glm::Mat4, target: &Framebuffer) -> u32 {
+        if self.draws.is_empty() { return 0 }
+        let draw_calls = self.draws.len() as u32;
+
+        // Nothing transparent hides anything else
+        gl::DepthMask(gl::FALSE);
+
+        match self.mode {
+            TransparencyMode::Sorted => {
+                // Furthest first, which is the most negative z in view space
+                let depth = |d: &TransparentDraw| (view * glm::vec4(d.center.x, d.center.y, d.center.z, 1.0)).z;
+                self.draws.sort_by(|a, b| depth(a).partial_cmp(&depth(b)).unwrap_or(std::cmp::Ordering::Equal));
+                shader.activate();
+                target.write_only_color(true);
+                for d in &self.draws {
+                    draw_with(shader, d);
+                }
+                target.write_only_color(false);
+            }
+            TransparencyMode::WeightedBlended => {
+                // Depth test against the opaque scene, in a buffer of our own
+                gl::BindFramebuffer(gl::READ_FRAMEBUFFER, target.framebuffer);
+                gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.accumulation.framebuffer);
+                let (w, h) = (target.width as i32, target.height as i32);
+                gl::BlitFramebuffer(0, 0, w, h, 0, 0, w, h, gl::DEPTH_BUFFER_BIT, gl::NEAREST);
+
+                self.accumulation.bind();
+                let (nothing, revealed) = ([0.0f32; 4], [1.0f32; 4]);
+                gl::ClearBufferfv(gl::COLOR, 0, nothing.as_ptr());
+                gl::ClearBufferfv(gl::COLOR, 1, revealed.as_ptr());
+                gl::BlendFunci(0, gl::ONE, gl::ONE);
+                gl::BlendFunci(1, gl::ZERO, gl::ONE_MINUS_SRC_COLOR);
+                gl::Disable(gl::CULL_FACE);
+
+                self.oit_shader.activate();
+                for d in &self.draws {
+                    draw_with(&self.oit_shader, d);
+                }
+                gl::Enable(gl::CULL_FACE);
+
+                // The average of the layers over the scene, as much as they cover it, filled in
+                // even if the layers were drawn as wireframes, which they are again afterwards
+                target.bind();
+                let mut polygon_mode = [gl::FILL as i32; 2];
+                gl::GetIntegerv(gl::POLYGON_MODE, polygon_mode.as_mut_ptr());
+                gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+                gl::Disable(gl::DEPTH_TEST);
+                gl::BlendFunc(gl::ONE_MINUS_SRC_ALPHA, gl::SRC_ALPHA);
+                target.write_only_color(true);
+                self.composite_shader.activate();
+                for (unit, name) in ["accumulation", "revealage"].iter().enumerate() {
+                    gl::Uniform1i(self.composite_shader.get_uniform_location(name), unit as i32);
+                    gl::ActiveTexture(gl::TEXTURE0 + unit as u32);
+                    gl::BindTexture(gl::TEXTURE_2D, self.accumulation.colors[unit]);
+                }
+                gl::BindVertexArray(self.empty_vao);
+                gl::DrawArrays(gl::TRIANGLES, 0, 3);
+                gl::PolygonMode(gl::FRONT_AND_BACK, polygon_mode[0] as u32);
+                target.write_only_color(false);
+                gl::ActiveTexture(gl::TEXTURE0);
+                gl::Enable(gl::DEPTH_TEST);
+                gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            }
+        }
+
+        gl::DepthMask(gl::TRUE);
+        self.draws.clear();
+        draw_calls
+    }
+}
+
+unsafe fn draw_with(shader: &Shader, d: &TransparentDraw) {
+    gl::UniformMatrix4fv(shader.get_uniform_location("model_matrix"), 1, gl::FALSE, glm::value_ptr(&d.model).as_ptr());
+    gl::Uniform4fv(shader.get_uniform_location("tint"), 1, glm::value_ptr(&d.tint).as_ptr());
+    gltf_import::bind_base_color(shader, d.texture_id);
+    gl::BindVertexArray(d.vao_id);
+    gl::DrawElements(gl::TRIANGLES, d.index_count, gl::UNSIGNED_INT, ptr::null());
+}
@@ -0,0 +1,264 @@
+extern crate nalgebra_glm as glm;
+
+use std::ffi::CString;
+
+use crate::shader::Shader;
+
+// Uniform buffer objects laid out by the std140 rules.
+//
+// A `BlockLayout` is built up field by field in the same order as the GLSL block, working out
+// where each field goes. A `UniformBuffer` holds the data for one such block on the CPU and the
+// GPU, and is bound to its binding point once. Every frame, set the fields and upload them all
+// in one go. `validate` checks the layout against what a linked shader expects.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Std140Type {
+    Float,
+    Int,
+    UInt,
+    Vec2,
+    Vec3,
+    Vec4,
+    Mat3,
+    Mat4,
+}
+
+impl Std140Type {
+    fn alignment(&self) -> usize {
+        match self {
+            Std140Type::Float | Std140Type::Int | Std140Type::UInt => 4,
+            Std140Type::Vec2 => 8,
+            _ => 16,
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Std140Type::Float | Std140Type::Int | Std140Type::UInt => 4,
+            Std140Type::Vec2 => 8,
+            Std140Type::Vec3 => 12,
+            Std140Type::Vec4 => 16,
+            Std140Type::Mat3 => 48, // Three columns, each padded to a vec4
+            Std140Type::Mat4 => 64,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Field {
+    pub name   : String,
+    pub kind   : Std140Type,
+    pub offset : usize,
+    pub length : Option<usize>, // For arrays
+    pub stride : usize,         // Between array elements, rounded up to a vec4
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockLayout {
+    pub name   : String, // Of the block in GLSL, not of any instance of it
+    pub fields : Vec<Field>,
+    end        : usize,  // Where the next field would start, before alignment
+}
+
+fn round_up(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+impl BlockLayout {
+    pub fn new(name: &str) -> Self {
+        BlockLayout { name: name.to_string(), fields: vec![], end: 0 }
+    }
+
+    pub fn field(mut self, name: &str, kind: Std140Type) -> Self {
+        let offset = round_up(self.end, kind.alignment());
+        self.end = offset + kind.size();
+        self.fields.push(Field { name: name.to_string(), kind, offset, length: None, stride: kind.size() });
+        self
+    }
+
+    // Array elements are aligned like vec4s, whatever their type
+    pub fn array(mut self, name: &str, kind: Std140Type, length: usize) -> Self {
+        let stride = round_up(kind.size(), 16);
+        let offset = round_up(self.end, 16);
+        self.end = offset + stride * length;
+        self.fields.push(Field { name: name.to_string(), kind, offset, length: Some(length), stride });
+        self
+    }
+
+    // The whole block, padded out to a vec4 like a struct
+    pub fn size(&self) -> usize {
+        round_up(self.end, 16)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+// Rust values that can go into a std140 block
+pub trait Std140Value {
+    const KIND: Std140Type;
+    fn write(&self, out: &mut [u8]);
+}
+
+fn write_floats(out: &mut [u8], values: &[f32]) {
+    for (i, v) in values.iter().enumerate() {
+        out[4 * i..4 * i + 4].copy_from_slice(&v.to_ne_bytes());
+    }
+}
+
+impl Std140Value for f32 {
+    const KIND: Std140Type = Std140Type::Float;
+    fn write(&self, out: &mut [u8]) { out[..4].copy_from_slice(&self.to_ne_bytes()) }
+}
+
+impl Std140Value for i32 {
+    const KIND: Std140Type = Std140Type::Int;
+    fn write(&self, out: &mut [u8]) { out[..4].copy_from_slice(&self.to_ne_bytes()) }
+}
+
+impl Std140Value for u32 {
+    const KIND: Std140Type = Std140Type::UInt;
+    fn write(&self, out: &mut [u8]) { out[..4].copy_from_slice(&self.to_ne_bytes()) }
+}
+
+impl Std140Value for glm::Vec2 {
+    const KIND: Std140Type = Std140Type::Vec2;
+    fn write(&self, out: &mut [u8]) { write_floats(out, self.as_slice()) }
+}
+
+impl Std140Value for glm::Vec3 {
+    const KIND: Std140Type = Std140Type::Vec3;
+    fn write(&self, out: &mut [u8]) { write_floats(out, self.as_slice()) }
+}
+
+impl Std140Value for glm::Vec4 {
+    const KIND: Std140Type = Std140Type::Vec4;
+    fn write(&self, out: &mut [u8]) { write_floats(out, self.as_slice()) }
+}
+
+impl Std140Value for glm::Mat3 {
+    const KIND: Std140Type = Std140Type::Mat3;
+    fn write(&self, out: &mut [u8]) {
+        for column in 0..3 {
+            write_floats(&mut out[16 * column..], self.column(column).as_slice());
+        }
+    }
+}
+
+impl Std140Value for glm::Mat4 {
+    const KIND: Std140Type = Std140Type::Mat4;
+    fn write(&self, out: &mut [u8]) { write_floats(out, self.as_slice()) }
+}
+
+pub struct UniformBuffer {
+    pub layout  : BlockLayout,
+    pub binding : u32,
+    buffer      : u32,
+    data        : Vec<u8>,
+}
+
+impl UniformBuffer {
+    // Creates the buffer and binds it to the binding point for good
+    pub unsafe fn new(layout: BlockLayout, binding: u32) -> Self {
+        let data = vec![0u8; layout.size()];
+        let mut buffer = 0;
+        gl::GenBuffers(1, &mut buffer);
+        gl::BindBuffer(gl::UNIFORM_BUFFER, buffer);
+        gl::BufferData(gl::UNIFORM_BUFFER, data.len() as isize, data.as_ptr() as *const _, gl::DYNAMIC_DRAW);
+        gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, buffer);
+        UniformBuffer { layout, binding, buffer, data }
+    }
+
+    // Panics if the block has no such field, or it is of another type
+    pub fn set<T: Std140Value>(&mut self, name: &str, value: &T) {
+        self.set_element(name, 0, value);
+    }
+
+    pub fn set_element<T: Std140Value>(&mut self, name: &str, index: usize, value: &T) {
+        let field = self.layout.get(name)
+            .unwrap_or_else(|| panic!("Uniform block {} has no field {}", self.layout.name, name));
+        assert!(field.kind == T::KIND, "Field {} of uniform block {} is a {:?}", name, self.layout.name, field.kind);
+        assert!(index < field.length.unwrap_or(1), "Index {} is out of bounds for {}", index, name);
+        let offset = field.offset + index * field.stride;
+        value.write(&mut self.data[offset..offset + field.kind.size()]);
+    }
+
+    // Sends every field to the GPU
+    pub unsafe fn upload(&self) {
+        gl::BindBuffer(gl::UNIFORM_BUFFER, self.buffer);
+        gl::BufferSubData(gl::UNIFORM_BUFFER, 0, self.data.len() as isize, self.data.as_ptr() as *const _);
+        gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+    }
+
+    // Asks the linked shader where it expects the block and its fields, and complains about
+    // anything that doesn't match. Fields the shader doesn't use are left out by the driver,
+    // so only those it does use are checked.
+    pub unsafe fn validate(&self, shader: &Shader) -> Result<(), String> {
+        let program = shader.program_id;
+        let block_name = CString::new(self.layout.name.as_str()).unwrap();
+        let block = gl::GetUniformBlockIndex(program, block_name.as_ptr());
+        if block == gl::INVALID_INDEX {
+            return Err(format!("Shader {} has no uniform block {}", program, self.layout.name));
+        }
+
+        let mut binding = 0;
+        gl::GetActiveUniformBlockiv(program, block, gl::UNIFORM_BLOCK_BINDING, &mut binding);
+        if binding as u32 != self.binding {
+            return Err(format!("Uniform block {} is bound to {} in shader {}, not {}", self.layout.name, binding, program, self.binding));
+        }
+
+        // std140 leaves it to the driver whether the padding after the last field is counted, so
+        // the block may be reported as ending right after its last field, or rounded up to a vec4
+        let mut size = 0;
+        gl::GetActiveUniformBlockiv(program, block, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+        if size as usize != self.layout.end && size as usize != self.layout.size() {
+            return Err(format!("Uniform block {} is {} bytes in shader {}, but {} bytes here", self.layout.name, size, program, self.layout.size()));
+        }
+
+        let mut count = 0;
+        gl::GetActiveUniformBlockiv(program, block, gl::UNIFORM_BLOCK_ACTIVE_UNIFORMS, &mut count);
+        let mut indices = vec![0i32; count as usize];
+        gl::GetActiveUniformBlockiv(program, block, gl::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES, indices.as_mut_ptr());
+
+        for &index in &indices {
+            let mut name = vec![0u8; 256];
+            let mut length = 0;
+            gl::GetActiveUniformName(program, index as u32, name.len() as i32, &mut length, name.as_mut_ptr() as *mut _);
+            let full_name = String::from_utf8_lossy(&name[..length as usize]).to_string();
+
+            // "Instance.field[0]" is just "field" to us
+            let short_name = full_name.rsplit('.').next().unwrap().split('[').next().unwrap();
+            let field = self.layout.get(short_name)
+                .ok_or(format!("Uniform block {} in shader {} has a field {} we don't know of", self.layout.name, program, full_name))?;
+
+            let mut offset = 0;
+            gl::GetActiveUniformsiv(program, 1, &(index as u32), gl::UNIFORM_OFFSET, &mut offset);
+            if offset as usize != field.offset {
+                return Err(format!("Field {} of uniform block {} is at offset {} in shader {}, but {} here", short_name, self.layout.name, offset, program, field.offset));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Everything about the camera that stays the same for a whole frame. In GLSL:
+//
+//     layout(std140, binding = 0) uniform Camera {
+//         mat4 view;
+//         mat4 projection;
+//         mat4 view_projection;
+//         vec3 camera_position;
+//         float time;
+//     };
+pub const CAMERA_BINDING: u32 = 0;
+
+pub fn camera_block() -> BlockLayout {
+    BlockLayout::new("Camera")
+        .field("view", Std140Type::Mat4)
+        .field("projection", Std140Type::Mat4)
+        .field("view_projection", Std140Type::Mat4)
+        .field("camera_position", Std140Type::Vec3)
+        .field("time", Std140Type::Float) // Fits in right after the vec3
+}